@@ -0,0 +1,280 @@
+use crate::{BrushType, DirectionVec, Layer, Line, Point};
+
+impl Layer {
+    /// Applies the eraser strokes of the layer to the lines drawn before
+    /// them, like the tablet shows them. Lines are cut where they enter and
+    /// leave an eraser stroke or area, lines that are erased completely are
+    /// removed. Afterwards, the layer holds no eraser lines.
+    pub fn apply_erasers(&mut self) {
+        let mut lines: Vec<Line> = Vec::with_capacity(self.lines.len());
+        for line in std::mem::take(&mut self.lines) {
+            let eraser = match line.brush_type {
+                BrushType::Eraser => Eraser::Stroke(&line.points),
+                BrushType::EraseArea => Eraser::Area(&line.points),
+                BrushType::EraseAll => continue,
+                _ => {
+                    lines.push(line);
+                    continue;
+                }
+            };
+            lines = lines
+                .into_iter()
+                .flat_map(|line| eraser.cut(line))
+                .collect();
+        }
+        self.lines = lines;
+    }
+}
+
+/// The region of an eraser line.
+enum Eraser<'a> {
+    /// Everything within half the width of the last point from the line.
+    Stroke(&'a [Point]),
+    /// Everything inside the polygon of the points.
+    Area(&'a [Point]),
+}
+
+impl Eraser<'_> {
+    fn contains(&self, point: &Point) -> bool {
+        match self {
+            Eraser::Stroke(points) => {
+                let radius = match points.last() {
+                    Some(last_point) => 0.5 * last_point.width,
+                    None => return false,
+                };
+                if points.len() == 1 {
+                    return points[0].distance(point) <= radius;
+                }
+                points
+                    .windows(2)
+                    .any(|segment| segment_distance(&segment[0], &segment[1], point) <= radius)
+            }
+            Eraser::Area(points) => {
+                // Even-odd rule: count the polygon edges crossing a ray to
+                // the right of the point.
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = &points[(i + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Cuts the parts of the line under the eraser out, splitting its
+    /// segments where they cross the border of the eraser.
+    fn cut(&self, line: Line) -> Vec<Line> {
+        if let [point] = &line.points[..] {
+            return if self.contains(point) {
+                Vec::new()
+            } else {
+                vec![line]
+            };
+        }
+
+        let mut pieces = Vec::new();
+        let mut piece = Vec::new();
+        for segment in line.points.windows(2) {
+            let (a, b) = (&segment[0], &segment[1]);
+            let at = |t: f32| match t {
+                t if t <= 0.0 => a.clone(),
+                t if t >= 1.0 => b.clone(),
+                t => interpolate(a, b, t),
+            };
+            for (start, end, erased) in self.intervals(a, b) {
+                if erased {
+                    if !piece.is_empty() {
+                        pieces.push(std::mem::take(&mut piece));
+                    }
+                } else {
+                    if piece.is_empty() {
+                        piece.push(at(start));
+                    }
+                    piece.push(at(end));
+                }
+            }
+        }
+        pieces.push(piece);
+
+        pieces
+            .into_iter()
+            .filter(|points| points.len() >= 2)
+            .map(|points| Line {
+                points,
+                ..line.clone_attributes()
+            })
+            .collect()
+    }
+
+    /// Splits the segment from `a` to `b` into parameter intervals that are
+    /// either erased or not, in order.
+    fn intervals(&self, a: &Point, b: &Point) -> Vec<(f32, f32, bool)> {
+        let mut ts = self.crossings(a, b);
+        ts.retain(|t| *t > 0.0 && *t < 1.0);
+        ts.extend([0.0, 1.0]);
+        ts.sort_by(f32::total_cmp);
+        ts.dedup();
+
+        let mut intervals: Vec<(f32, f32, bool)> = Vec::new();
+        for t in ts.windows(2) {
+            let erased = self.contains(&interpolate(a, b, 0.5 * (t[0] + t[1])));
+            match intervals.last_mut() {
+                // Not every candidate is an actual crossing.
+                Some(last) if last.2 == erased => last.1 = t[1],
+                _ => intervals.push((t[0], t[1], erased)),
+            }
+        }
+        intervals
+    }
+
+    /// Parameters at which the segment from `a` to `b` may cross the border
+    /// of the eraser.
+    fn crossings(&self, a: &Point, b: &Point) -> Vec<f32> {
+        let direction = b - a;
+        let mut ts = Vec::new();
+        match self {
+            Eraser::Stroke(points) => {
+                let radius = match points.last() {
+                    Some(last_point) => 0.5 * last_point.width,
+                    None => return ts,
+                };
+                // The border consists of circles around the points and the
+                // sides of the segments in between.
+                for center in points.iter() {
+                    let offset = a - center;
+                    let qa = direction.dot(&direction);
+                    let qb = 2.0 * direction.dot(&offset);
+                    let qc = offset.dot(&offset) - radius * radius;
+                    let discriminant = qb * qb - 4.0 * qa * qc;
+                    if qa > 0.0 && discriminant >= 0.0 {
+                        let root = discriminant.sqrt();
+                        ts.extend([(-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa)]);
+                    }
+                }
+                for segment in points.windows(2) {
+                    let side = &segment[1] - &segment[0];
+                    let normal = side.rotate_orthogonally().set_length(radius);
+                    for normal in [normal, -normal] {
+                        let start = &segment[0] + &normal;
+                        ts.extend(segment_intersection(a, direction, &start, side));
+                    }
+                }
+            }
+            Eraser::Area(points) => {
+                for (i, start) in points.iter().enumerate() {
+                    let side = &points[(i + 1) % points.len()] - start;
+                    ts.extend(segment_intersection(a, direction, start, side));
+                }
+            }
+        }
+        ts
+    }
+}
+
+/// The parameter along the first segment at which it intersects the
+/// second one, both given by start and direction.
+fn segment_intersection(
+    start: &Point,
+    direction: DirectionVec,
+    other_start: &Point,
+    other_direction: DirectionVec,
+) -> Option<f32> {
+    let denominator = direction.cross(&other_direction);
+    if denominator == 0.0 {
+        return None;
+    }
+    let offset = other_start - start;
+    let t = offset.cross(&other_direction) / denominator;
+    let u = offset.cross(&direction) / denominator;
+    (0.0..=1.0).contains(&u).then_some(t)
+}
+
+/// Distance of `point` from the segment between `a` and `b`.
+fn segment_distance(a: &Point, b: &Point, point: &Point) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return a.distance(point);
+    }
+    let t = (((point.x - a.x) * dx + (point.y - a.y) * dy) / length_squared).clamp(0.0, 1.0);
+    interpolate(a, b, t).distance(point)
+}
+
+fn interpolate(a: &Point, b: &Point, t: f32) -> Point {
+    let lerp = |a: f32, b: f32| a + t * (b - a);
+    Point {
+        x: lerp(a.x, b.x),
+        y: lerp(a.y, b.y),
+        speed: lerp(a.speed, b.speed),
+        direction: lerp(a.direction, b.direction),
+        width: lerp(a.width, b.width),
+        pressure: lerp(a.pressure, b.pressure),
+    }
+}
+
+#[test]
+fn test_apply_erasers() {
+    let horizontal: Vec<(f32, f32)> = (0..=10).map(|x| (10.0 * x as f32, 0.0)).collect();
+    let mut layer = Layer {
+        lines: vec![
+            Line::with_brush(BrushType::Marker, 2.0, &horizontal),
+            Line::with_brush(BrushType::Fineliner, 2.0, &[(50.0, -2.0), (50.0, 2.0)]),
+            Line::with_brush(BrushType::Eraser, 20.0, &[(50.0, -50.0), (50.0, 50.0)]),
+            Line::with_brush(
+                BrushType::EraseArea,
+                1.0,
+                &[(80.0, -5.0), (200.0, -5.0), (200.0, 5.0), (80.0, 5.0)],
+            ),
+            Line::with_brush(BrushType::Marker, 2.0, &horizontal),
+        ],
+        ..Default::default()
+    };
+    layer.apply_erasers();
+
+    let xs = |line: &Line| line.points.iter().map(|p| p.x.round()).collect::<Vec<_>>();
+    assert_eq!(layer.lines.len(), 3);
+    // The fineliner line is erased completely, the first marker line is cut
+    // by the eraser stroke and the eraser area.
+    assert_eq!(xs(&layer.lines[0]), vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+    assert_eq!(xs(&layer.lines[1]), vec![60.0, 70.0, 80.0]);
+    assert!(matches!(layer.lines[1].brush_type, BrushType::Marker));
+    // Lines drawn after the erasers are kept.
+    assert_eq!(layer.lines[2].points.len(), 11);
+}
+
+#[test]
+fn test_apply_erasers_between_points() {
+    let mut layer = Layer {
+        lines: vec![
+            Line::with_brush(BrushType::Fineliner, 2.0, &[(0.0, 100.0), (100.0, 100.0)]),
+            Line::with_brush(BrushType::Eraser, 20.0, &[(50.0, 50.0), (50.0, 150.0)]),
+            Line::with_brush(BrushType::Fineliner, 2.0, &[(0.0, 0.0), (100.0, 100.0)]),
+            Line::with_brush(
+                BrushType::EraseArea,
+                1.0,
+                &[(40.0, 0.0), (60.0, 0.0), (60.0, 100.0), (40.0, 100.0)],
+            ),
+        ],
+        ..Default::default()
+    };
+    layer.apply_erasers();
+
+    // Both lines are cut where they cross the eraser, although none of their
+    // points are under it.
+    let ends = |line: &Line| {
+        line.points
+            .iter()
+            .map(|p| (p.x.round(), p.y.round()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(layer.lines.len(), 4);
+    assert_eq!(ends(&layer.lines[0]), vec![(0.0, 100.0), (40.0, 100.0)]);
+    assert_eq!(ends(&layer.lines[1]), vec![(60.0, 100.0), (100.0, 100.0)]);
+    assert_eq!(ends(&layer.lines[2]), vec![(0.0, 0.0), (40.0, 40.0)]);
+    assert_eq!(ends(&layer.lines[3]), vec![(60.0, 60.0), (100.0, 100.0)]);
+}
@@ -0,0 +1,114 @@
+use crate::{Line, Point};
+
+/// Factor from pressure differences to the units of the tolerance: with a
+/// tolerance of 1, pressure changes of more than 0.1 are kept.
+const PRESSURE_SCALE: f32 = 10.0;
+
+impl Line {
+    /// Returns the line with the points left out that deviate at most
+    /// `tolerance` from the simplified line, using the Ramer–Douglas–Peucker
+    /// algorithm. Corners are kept, and so are points whose width or
+    /// pressure differs by more than `tolerance` from the one interpolated
+    /// between the kept points, see `PRESSURE_SCALE`. The first and last
+    /// point are always kept.
+    pub fn simplified(&self, tolerance: f32) -> Line {
+        let points = &self.points;
+        let mut keep = vec![false; points.len()];
+        if !points.is_empty() {
+            keep[0] = true;
+            keep[points.len() - 1] = true;
+        }
+
+        let mut ranges = vec![(0, points.len().saturating_sub(1))];
+        while let Some((start, end)) = ranges.pop() {
+            if end <= start + 1 {
+                continue;
+            }
+            let (max_error, split) = (start + 1..end)
+                .map(|i| (deviation(&points[start], &points[end], &points[i]), i))
+                .fold(
+                    (0.0, start),
+                    |max, error| if error.0 > max.0 { error } else { max },
+                );
+            if max_error > tolerance {
+                keep[split] = true;
+                ranges.push((start, split));
+                ranges.push((split, end));
+            }
+        }
+
+        Line {
+            points: points
+                .iter()
+                .zip(keep)
+                .filter(|(_, keep)| *keep)
+                .map(|(point, _)| point.clone())
+                .collect(),
+            ..self.clone_attributes()
+        }
+    }
+}
+
+/// How far the point is off the segment between `a` and `b`, in position,
+/// width or pressure.
+fn deviation(a: &Point, b: &Point, point: &Point) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((point.x - a.x) * dx + (point.y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+    let (x, y) = (a.x + t * dx, a.y + t * dy);
+    let width = a.width + t * (b.width - a.width);
+    let pressure = a.pressure + t * (b.pressure - a.pressure);
+    let distance = ((point.x - x).powi(2) + (point.y - y).powi(2)).sqrt();
+    distance
+        .max((point.width - width).abs())
+        .max((point.pressure - pressure).abs() * PRESSURE_SCALE)
+}
+
+#[test]
+fn test_simplified() {
+    let mut line = Line::with_points(
+        Point {
+            width: 2.0,
+            ..Default::default()
+        },
+        &[
+            (0.0, 0.0),
+            (10.0, 0.1),
+            (20.0, -0.1),
+            (30.0, 0.0),
+            (30.0, 10.0),
+            (30.0, 20.0),
+            (30.0, 30.0),
+        ],
+    );
+    line.points[5].width = 4.0;
+    let coordinates = |line: &Line| line.points.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>();
+
+    // The corner and the wider point are kept.
+    assert_eq!(
+        coordinates(&line.simplified(1.5)),
+        vec![(0.0, 0.0), (30.0, 0.0), (30.0, 20.0), (30.0, 30.0)]
+    );
+    assert_eq!(line.simplified(0.0).points.len(), 7);
+
+    // So are points where the pressure differs from the interpolated one.
+    line.points[5].width = 2.0;
+    line.points[5].pressure = 0.5;
+    assert_eq!(
+        coordinates(&line.simplified(1.5)),
+        vec![
+            (0.0, 0.0),
+            (30.0, 0.0),
+            (30.0, 10.0),
+            (30.0, 20.0),
+            (30.0, 30.0)
+        ]
+    );
+    line.points[5].pressure = 0.05;
+    assert_eq!(coordinates(&line.simplified(1.5)).len(), 3);
+    assert_eq!(Line::default().simplified(1.0).points.len(), 0);
+}
@@ -0,0 +1,89 @@
+use crate::{Layer, Line, Matrix, Page, Point};
+
+impl Page {
+    /// Transforms all lines of the page, see `Line::transform`.
+    pub fn transform(&mut self, matrix: Matrix) {
+        for layer in self.layers.iter_mut() {
+            layer.transform(matrix);
+        }
+    }
+}
+
+impl Layer {
+    /// Transforms all lines of the layer, see `Line::transform`.
+    pub fn transform(&mut self, matrix: Matrix) {
+        for line in self.lines.iter_mut() {
+            line.transform(matrix);
+        }
+    }
+}
+
+impl Line {
+    /// Transforms the points of the line. Widths are scaled by
+    /// `Matrix::length_scale`, so uneven scaling gives strokes of an average
+    /// width. Speed, direction and pressure are kept as they are.
+    pub fn transform(&mut self, matrix: Matrix) {
+        for point in self.points.iter_mut() {
+            point.transform(matrix);
+        }
+    }
+}
+
+impl Point {
+    /// Transforms the position of the point and scales its width, see
+    /// `Line::transform`.
+    pub fn transform(&mut self, matrix: Matrix) {
+        let [x, y] = matrix * [self.x, self.y];
+        self.x = x;
+        self.y = y;
+        self.width *= matrix.length_scale();
+    }
+}
+
+#[test]
+fn test_transform_page() {
+    use crate::render::renderlib::{PAGE_HEIGHT, PAGE_WIDTH};
+    use std::f32::consts::FRAC_PI_2;
+
+    let line = Line::with_points(
+        Point {
+            width: 2.0,
+            ..Default::default()
+        },
+        &[(0.0, 0.0), (PAGE_WIDTH, 100.0)],
+    );
+    let mut page = Page {
+        layers: vec![Layer {
+            lines: vec![line],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let coordinates = |page: &Page| {
+        page.layers[0].lines[0]
+            .points
+            .iter()
+            .map(|p| (p.x.round(), p.y.round(), p.width))
+            .collect::<Vec<_>>()
+    };
+
+    // Rotating clockwise by a quarter turn puts the portrait page on its
+    // side, its left edge at the top of the landscape page.
+    page.transform(Matrix::translate(PAGE_HEIGHT, 0.0) * Matrix::rotate(FRAC_PI_2));
+    assert_eq!(
+        coordinates(&page),
+        vec![
+            (PAGE_HEIGHT, 0.0, 2.0),
+            (PAGE_HEIGHT - 100.0, PAGE_WIDTH, 2.0)
+        ]
+    );
+
+    page.transform(Matrix::scale(0.5, 0.5) * Matrix::mirror_y(0.0));
+    assert_eq!(
+        coordinates(&page),
+        vec![
+            (0.5 * PAGE_HEIGHT, 0.0, 1.0),
+            (0.5 * (PAGE_HEIGHT - 100.0), -0.5 * PAGE_WIDTH, 1.0)
+        ]
+    );
+}
@@ -1,15 +1,33 @@
 pub mod render {
+    pub(crate) mod curves;
     pub mod pdf;
+    pub mod png;
     pub mod renderlib;
     pub mod svg;
     pub mod templates;
 }
 pub mod parse {
     pub mod parse_lines;
-}
-pub use render::pdf::render_pdf;
-pub use render::svg::render_svg;
-use std::ops::{Add, Div, Mul, Sub};
+    pub mod parse_notebook;
+    pub mod parse_v6;
+}
+pub mod write {
+    pub mod write_lines;
+}
+pub mod edit {
+    pub mod erase;
+    pub mod simplify;
+    pub mod transform;
+}
+pub use parse::parse_lines::ParseOptions;
+pub use render::pdf::{render_pdf, PdfRenderer};
+pub use render::png::{render_png, PngRenderer};
+pub use render::renderlib::{
+    css_color_to_rgb, render_pages, stroke_outline, BoundingBox, EraseStyle, RenderOptions,
+    Renderer, StrokeStyle,
+};
+pub use render::svg::{render_svg, SvgRenderer};
+use std::ops::{Add, Div, Mul, Neg, Sub};
 use thiserror::Error;
 
 use std::convert::TryFrom;
@@ -19,6 +37,12 @@ pub enum Error {
     #[error("Unsupported version string: {0}")]
     UnsupportedVersion(String),
 
+    #[error("Version {0} files hold exactly one page, got {1}")]
+    UnsupportedPageCount(i32, usize),
+
+    #[error("{0} output holds a single page")]
+    MultiplePages(&'static str),
+
     #[error("Unknown brush type: {0}")]
     UnknownBrush(i32),
 
@@ -28,12 +52,37 @@ pub enum Error {
     #[error("Unknown template: '{0}' Valid templates are:\n  {}", crate::render::templates::TEMPLATES.keys().copied().map(|t| format!("\n  {}", t)).collect::<String>())]
     UnknownTemplate(String),
 
+    #[error("Invalid template '{0}': {1}")]
+    InvalidTemplate(String, String),
+
+    #[error("Unexpected tag at offset {offset}: expected index {index} of type {tag_type:#x}")]
+    UnexpectedTag {
+        offset: usize,
+        index: u64,
+        tag_type: u8,
+    },
+
+    #[error("Unexpected scene item type: {0:#x}")]
+    UnexpectedItemType(u8),
+
     #[error("Invalid Segment index: {0}")]
     InvalidSegmentIndex(usize),
 
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("No <uuid>.content file found in archive")]
+    MissingContent,
+
+    #[error("More than one <uuid>.content file found in archive")]
+    MultipleContents,
+
+    #[error(transparent)]
+    ZipError(#[from] zip::result::ZipError),
+
     #[error(transparent)]
     TryFromIntError(#[from] std::num::TryFromIntError),
 }
@@ -43,22 +92,52 @@ type Result<T> = core::result::Result<T, Error>;
 pub struct LinesData {
     pub version: i32,
     pub pages: Vec<Page>,
+    /// Problems tolerated by lenient parsing, see `ParseOptions::lenient`.
+    pub warnings: Vec<Error>,
+}
+
+/// A notebook as stored by the tablet: a `<uuid>.content` file listing the
+/// pages and a `<uuid>` directory holding one `.rm` file per page.
+#[derive(Debug, Default)]
+pub struct Notebook {
+    pub uuid: String,
+    pub pages: Vec<Page>,
+    /// Problems tolerated by lenient parsing, see `ParseOptions::lenient`.
+    pub warnings: Vec<Error>,
 }
 
 #[derive(Default, Debug)]
 pub struct Page {
     pub layers: Vec<Layer>,
+    /// Name of the page's background template, see `render::templates`.
+    pub template: Option<String>,
+    pub orientation: Orientation,
+}
+
+/// How a page is held on the tablet. Lines are always stored in portrait
+/// coordinates, landscape pages are turned a quarter clockwise for viewing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
 }
 
 #[derive(Default, Debug)]
 pub struct Layer {
     pub lines: Vec<Line>,
+    /// The name given to the layer on the tablet, if known.
+    pub name: Option<String>,
+    /// Whether the layer is hidden on the tablet. Hidden layers are not
+    /// rendered unless requested.
+    pub hidden: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum BrushType {
     BallPoint,
     Marker,
+    #[default]
     Fineliner,
     SharpPencil,
     TiltPencil,
@@ -68,76 +147,166 @@ pub enum BrushType {
     EraseArea,
     EraseAll,
     Calligraphy,
+    /// The ballpoint pen of the first brush generation, code 2. Unlike
+    /// `BallPoint` its strokes do not get lighter with decreasing pressure.
     Pen,
     SelectionBrush,
-}
-
-impl Default for BrushType {
-    fn default() -> BrushType {
-        BrushType::Fineliner
-    }
+    /// A brush code we do not know, kept by lenient parsing, see
+    /// `ParseOptions::lenient`. Drawn like a fineliner.
+    Unknown(i32),
 }
 
 impl std::convert::TryFrom<i32> for BrushType {
     type Error = Error;
 
     fn try_from(value: i32) -> Result<Self> {
-        match value {
+        match BrushType::from_code(value) {
+            BrushType::Unknown(code) => Err(Error::UnknownBrush(code)),
+            brush_type => Ok(brush_type),
+        }
+    }
+}
+
+impl BrushType {
+    /// The brush stored under the code, `BrushType::Unknown` for codes we do
+    /// not know.
+    pub fn from_code(code: i32) -> BrushType {
+        match code {
             // There seem to be different "versions" of similar brushes (e.g.
             // "Brush" at 0 and 12). v3 seems e.g. to use Brush 0 while v5 seems
             // to use Brush 12.
-            0 => Ok(BrushType::Brush),
-            1 => Ok(BrushType::TiltPencil),
-            2 => Ok(BrushType::Pen),
-            3 => Ok(BrushType::Marker),
-            4 => Ok(BrushType::Fineliner),
-            5 => Ok(BrushType::Highlighter),
-            6 => Ok(BrushType::Eraser),
-            7 => Ok(BrushType::SharpPencil),
-            8 => Ok(BrushType::EraseArea),
-            9 => Ok(BrushType::EraseAll),
-            10 => Ok(BrushType::SelectionBrush),
-            11 => Ok(BrushType::SelectionBrush),
-            12 => Ok(BrushType::Brush),
-            13 => Ok(BrushType::SharpPencil),
-            14 => Ok(BrushType::TiltPencil),
-            15 => Ok(BrushType::BallPoint),
-            16 => Ok(BrushType::Marker),
-            17 => Ok(BrushType::Fineliner),
-            18 => Ok(BrushType::Highlighter),
-            21 => Ok(BrushType::Calligraphy),
-            v => Err(Error::UnknownBrush(v)),
+            0 => BrushType::Brush,
+            1 => BrushType::TiltPencil,
+            2 => BrushType::Pen,
+            3 => BrushType::Marker,
+            4 => BrushType::Fineliner,
+            5 => BrushType::Highlighter,
+            6 => BrushType::Eraser,
+            7 => BrushType::SharpPencil,
+            8 => BrushType::EraseArea,
+            9 => BrushType::EraseAll,
+            10 => BrushType::SelectionBrush,
+            11 => BrushType::SelectionBrush,
+            12 => BrushType::Brush,
+            13 => BrushType::SharpPencil,
+            14 => BrushType::TiltPencil,
+            15 => BrushType::BallPoint,
+            16 => BrushType::Marker,
+            17 => BrushType::Fineliner,
+            18 => BrushType::Highlighter,
+            21 => BrushType::Calligraphy,
+            code => BrushType::Unknown(code),
+        }
+    }
+
+    /// The code under which this brush is stored in a file of the given
+    /// version. Version 5 introduced new codes for most brushes, see
+    /// `BrushType::from_code`.
+    pub fn code(&self, version: i32) -> i32 {
+        let (code, v5_code) = match self {
+            BrushType::Brush => (0, 12),
+            BrushType::TiltPencil => (1, 14),
+            BrushType::Pen => (2, 2),
+            BrushType::Marker => (3, 16),
+            BrushType::Fineliner => (4, 17),
+            BrushType::Highlighter => (5, 18),
+            BrushType::Eraser => (6, 6),
+            BrushType::SharpPencil => (7, 13),
+            BrushType::EraseArea => (8, 8),
+            BrushType::EraseAll => (9, 9),
+            BrushType::SelectionBrush => (10, 10),
+            BrushType::BallPoint => (15, 15),
+            BrushType::Calligraphy => (21, 21),
+            BrushType::Unknown(code) => (*code, *code),
+        };
+        if version >= 5 {
+            v5_code
+        } else {
+            code
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
+    #[default]
     Black,
     Grey,
     White,
     Blue,
     Red,
+    Yellow,
+    Green,
+    Pink,
+    /// The grey of newer firmware, drawn like `Grey`.
+    GreyOverlap,
+    /// The highlighter color of newer firmware, whose actual color is stored
+    /// in `Line::argb`. Drawn in the layer's highlight color without one.
+    Highlight,
+    LightGreen,
+    Cyan,
+    Magenta,
+    LightYellow,
+    /// A color code we do not know, kept by lenient parsing, see
+    /// `ParseOptions::lenient`. Drawn in `Line::argb` if the line has one,
+    /// in the layer's black otherwise.
+    Unknown(i32),
 }
 
 impl TryFrom<i32> for Color {
     type Error = Error;
 
     fn try_from(color_i: i32) -> Result<Self> {
-        match color_i {
-            0 => Ok(Color::Black),
-            1 => Ok(Color::Grey),
-            2 => Ok(Color::White),
-            6 => Ok(Color::Blue),
-            7 => Ok(Color::Red),
-            _ => Err(Error::UnknownColor(color_i)),
+        match Color::from_code(color_i) {
+            Color::Unknown(code) => Err(Error::UnknownColor(code)),
+            color => Ok(color),
+        }
+    }
+}
+
+impl Color {
+    /// The color stored under the code, `Color::Unknown` for codes we do
+    /// not know.
+    pub fn from_code(code: i32) -> Color {
+        match code {
+            0 => Color::Black,
+            1 => Color::Grey,
+            2 => Color::White,
+            3 => Color::Yellow,
+            4 => Color::Green,
+            5 => Color::Pink,
+            6 => Color::Blue,
+            7 => Color::Red,
+            8 => Color::GreyOverlap,
+            9 => Color::Highlight,
+            10 => Color::LightGreen,
+            11 => Color::Cyan,
+            12 => Color::Magenta,
+            13 => Color::LightYellow,
+            code => Color::Unknown(code),
         }
     }
 }
 
-impl Default for Color {
-    fn default() -> Color {
-        Color::Black
+impl From<Color> for i32 {
+    fn from(color: Color) -> i32 {
+        match color {
+            Color::Black => 0,
+            Color::Grey => 1,
+            Color::White => 2,
+            Color::Yellow => 3,
+            Color::Green => 4,
+            Color::Pink => 5,
+            Color::Blue => 6,
+            Color::Red => 7,
+            Color::GreyOverlap => 8,
+            Color::Highlight => 9,
+            Color::LightGreen => 10,
+            Color::Cyan => 11,
+            Color::Magenta => 12,
+            Color::LightYellow => 13,
+            Color::Unknown(code) => code,
+        }
     }
 }
 
@@ -145,6 +314,15 @@ impl Default for Color {
 pub struct Line {
     pub brush_type: BrushType,
     pub color: Color,
+    /// The code `brush_type` was read from. Several codes share a brush type,
+    /// see `BrushType::from_code`. `None` for lines not read from a file.
+    pub brush_code: Option<i32>,
+    /// The code `color` was read from, `None` for lines not read from a file.
+    pub color_code: Option<i32>,
+    /// The exact color as `0xAARRGGBB`, stored by newer firmware in version 6
+    /// files. Used when rendering `Color::Highlight` and unknown colors,
+    /// which have no layer color of their own.
+    pub argb: Option<u32>,
     pub unknown_line_attribute: i32,
     pub unknown_line_attribute_2: i32,
     pub brush_base_size: f32,
@@ -152,7 +330,8 @@ pub struct Line {
 }
 
 impl Line {
-    fn segment_length(&self, i: usize) -> Result<f32> {
+    /// Length of the segment from point `i` to point `i + 1`.
+    pub fn segment_length(&self, i: usize) -> Result<f32> {
         if i + 1 >= self.points.len() {
             Err(Error::InvalidSegmentIndex(i))
         } else {
@@ -160,17 +339,18 @@ impl Line {
         }
     }
 
-    fn length(&self) -> f32 {
+    /// Length of the line along its points.
+    pub fn length(&self) -> f32 {
         self.points
             .iter()
-            .zip(self.points[1..].iter())
+            .zip(self.points.iter().skip(1))
             .map(|(previous_point, point)| previous_point.distance(point))
             .sum()
     }
 
     /// Average of each segment's width, weighted by the segment length.
     /// Primarily useful for rendering to targets requiring a fixed line width.
-    fn average_width(&self) -> f32 {
+    pub fn average_width(&self) -> f32 {
         // TODO: Are the width values of the first and second point always the same?
 
         // Algorithm for weighted average see e.g. notes by Tony Finch:
@@ -178,14 +358,35 @@ impl Line {
         // https://fanf2.user.srcf.net/hermes/doc/antiforgery/stats.pdf#page=3
         let mut average_width = 0.0;
         let mut total_length = 0.0;
-        for (i, point) in self.points[1..].iter().enumerate() {
+        for (i, point) in self.points.iter().skip(1).enumerate() {
             let segment_length = self.segment_length(i).unwrap_or_else(|_| unreachable!());
             total_length += segment_length;
-            average_width += segment_length / total_length * (point.width - average_width);
+            if total_length > 0.0 {
+                average_width += segment_length / total_length * (point.width - average_width);
+            }
         }
         average_width
     }
 
+    /// Smallest and largest width of the points, `None` for lines without
+    /// points.
+    pub fn width_range(&self) -> Option<(f32, f32)> {
+        let mut widths = self.points.iter().map(|point| point.width);
+        let first_width = widths.next()?;
+        Some(
+            widths.fold((first_width, first_width), |(min, max), width| {
+                (min.min(width), max.max(width))
+            }),
+        )
+    }
+
+    /// Generation of the line's brush: 2 for the brushes redesigned with
+    /// firmware 2, stored under codes from 12 on, 1 for the others. `None`
+    /// for lines not read from a file.
+    pub fn brush_generation(&self) -> Option<u8> {
+        self.brush_code.map(|code| if code >= 12 { 2 } else { 1 })
+    }
+
     /// Produces the offset vectors for each line segment for creating a offset
     /// polyline. Each offset vector indicates the direction and distance for
     /// offsetting the line segment. The offset vector can be mirrored to get
@@ -200,6 +401,23 @@ impl Line {
             .collect()
     }
 
+    /// A line with the same attributes, but without points.
+    fn clone_attributes(&self) -> Line {
+        Line {
+            brush_type: self.brush_type,
+            color: self.color,
+            brush_code: self.brush_code,
+            color_code: self.color_code,
+            argb: self.argb,
+            unknown_line_attribute: self.unknown_line_attribute,
+            unknown_line_attribute_2: self.unknown_line_attribute_2,
+            brush_base_size: self.brush_base_size,
+            points: Vec::new(),
+        }
+    }
+
+    /// A line through the coordinates with copies of the template point.
+    #[cfg(test)]
     fn with_points(template: Point, points: &[(f32, f32)]) -> Line {
         Line {
             points: points
@@ -214,6 +432,22 @@ impl Line {
             ..Default::default()
         }
     }
+
+    /// A line of the brush through the coordinates, with points of the
+    /// given width.
+    #[cfg(test)]
+    fn with_brush(brush_type: BrushType, width: f32, points: &[(f32, f32)]) -> Line {
+        Line {
+            brush_type,
+            ..Line::with_points(
+                Point {
+                    width,
+                    ..Default::default()
+                },
+                points,
+            )
+        }
+    }
 }
 
 #[test]
@@ -235,6 +469,33 @@ fn test_line_offsets() {
     );
 }
 
+#[test]
+fn test_line_geometry() {
+    let mut line = Line::with_points(
+        Point {
+            width: 2.0,
+            ..Default::default()
+        },
+        &[(0.0, 0.0), (3.0, 4.0), (3.0, 14.0)],
+    );
+    line.points[2].width = 5.0;
+    assert_eq!(line.length(), 15.0);
+    assert_eq!(line.segment_length(1).unwrap(), 10.0);
+    assert!(line.segment_length(2).is_err());
+    assert_eq!(line.average_width(), (5.0 * 2.0 + 10.0 * 5.0) / 15.0);
+    assert_eq!(line.width_range(), Some((2.0, 5.0)));
+    assert_eq!(Line::default().length(), 0.0);
+    assert_eq!(Line::default().average_width(), 0.0);
+    assert_eq!(Line::default().width_range(), None);
+
+    let v = &line.points[1] - &line.points[0];
+    assert_eq!(v.length(), 5.0);
+    assert_eq!(v.rotate_orthogonally(), DirectionVec::new(-4.0, 3.0));
+    assert_eq!(v.dot(&v.rotate_orthogonally()), 0.0);
+    assert_eq!(v.cross(&v.rotate_orthogonally()), 25.0);
+    assert_eq!(-v + v, DirectionVec::ZERO);
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Point {
     pub x: f32,
@@ -246,12 +507,13 @@ pub struct Point {
 }
 
 impl Point {
-    fn distance(&self, point: &Point) -> f32 {
+    /// Distance between the centers of the points.
+    pub fn distance(&self, point: &Point) -> f32 {
         ((self.x - point.x).powi(2) + (self.y - point.y).powi(2)).sqrt()
     }
 }
 
-impl<'a, 'b> Sub<&'b Point> for &'a Point {
+impl Sub<&Point> for &Point {
     type Output = DirectionVec;
 
     fn sub(self, other: &Point) -> DirectionVec {
@@ -262,7 +524,7 @@ impl<'a, 'b> Sub<&'b Point> for &'a Point {
     }
 }
 
-impl<'a, 'b> Add<&'b DirectionVec> for &'a Point {
+impl Add<&DirectionVec> for &Point {
     type Output = Point;
 
     fn add(self, other: &DirectionVec) -> Point {
@@ -274,7 +536,7 @@ impl<'a, 'b> Add<&'b DirectionVec> for &'a Point {
     }
 }
 
-impl<'a, 'b> Sub<&'b DirectionVec> for &'a Point {
+impl Sub<&DirectionVec> for &Point {
     type Output = Point;
 
     fn sub(self, other: &DirectionVec) -> Point {
@@ -286,20 +548,26 @@ impl<'a, 'b> Sub<&'b DirectionVec> for &'a Point {
     }
 }
 
-#[derive(Default, Debug, PartialEq)]
+/// A 2D vector, e.g. the difference of two points.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct DirectionVec {
-    x: f32,
-    y: f32,
+    pub x: f32,
+    pub y: f32,
 }
 
 impl DirectionVec {
-    const ZERO: DirectionVec = DirectionVec { x: 0.0, y: 0.0 };
+    pub const ZERO: DirectionVec = DirectionVec { x: 0.0, y: 0.0 };
+
+    pub fn new(x: f32, y: f32) -> DirectionVec {
+        DirectionVec { x, y }
+    }
 
-    fn length(&self) -> f32 {
+    pub fn length(&self) -> f32 {
         (self.x.powi(2) + self.y.powi(2)).sqrt()
     }
 
-    fn set_length(mut self, length: f32) -> DirectionVec {
+    /// Scales the vector to the given length. The zero vector stays as is.
+    pub fn set_length(mut self, length: f32) -> DirectionVec {
         let factor = self.length() / length;
         if factor != 0.0 {
             self.x /= factor;
@@ -308,11 +576,22 @@ impl DirectionVec {
         self
     }
 
-    fn rotate_orthogonally(mut self) -> DirectionVec {
+    /// Rotates the vector by 90 degrees, from the x axis towards the y axis.
+    pub fn rotate_orthogonally(mut self) -> DirectionVec {
         std::mem::swap(&mut self.x, &mut self.y);
         self.x *= -1.0;
         self
     }
+
+    pub fn dot(&self, other: &DirectionVec) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// The z component of the cross product, positive if `other` points to
+    /// the side that `rotate_orthogonally` turns to.
+    pub fn cross(&self, other: &DirectionVec) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
 }
 
 impl Add for DirectionVec {
@@ -326,6 +605,28 @@ impl Add for DirectionVec {
     }
 }
 
+impl Sub for DirectionVec {
+    type Output = DirectionVec;
+
+    fn sub(self, other: DirectionVec) -> DirectionVec {
+        DirectionVec {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl Neg for DirectionVec {
+    type Output = DirectionVec;
+
+    fn neg(self) -> DirectionVec {
+        DirectionVec {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
+}
+
 impl Mul<f32> for DirectionVec {
     type Output = DirectionVec;
 
@@ -353,7 +654,54 @@ impl Div<f32> for DirectionVec {
 // [ a b 0 ]
 // [ c d 0 ]
 // [ e f 1 ]
-pub struct Matrix([f32; 6]);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix(pub [f32; 6]);
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Constructors for the basic transformations. Combine them by multiplying,
+/// `a * b` applies `b` first, then `a`.
+impl Matrix {
+    pub fn identity() -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+    }
+
+    pub fn translate(x: f32, y: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    pub fn scale(x: f32, y: f32) -> Matrix {
+        Matrix([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    /// Rotation around the origin by `angle` radians, from the x axis
+    /// towards the y axis. With the y axis pointing down as on the page,
+    /// positive angles rotate clockwise.
+    pub fn rotate(angle: f32) -> Matrix {
+        let (sin, cos) = angle.sin_cos();
+        Matrix([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    /// Mirrors x coordinates at the vertical line through `x`.
+    pub fn mirror_x(x: f32) -> Matrix {
+        Matrix([-1.0, 0.0, 0.0, 1.0, 2.0 * x, 0.0])
+    }
+
+    /// Mirrors y coordinates at the horizontal line through `y`.
+    pub fn mirror_y(y: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, -1.0, 0.0, 2.0 * y])
+    }
+
+    /// Factor by which the matrix scales lengths, the geometric mean of the
+    /// scaling along its axes.
+    pub fn length_scale(&self) -> f32 {
+        (self.0[0] * self.0[3] - self.0[1] * self.0[2]).abs().sqrt()
+    }
+}
 
 impl Mul<Matrix> for Matrix {
     type Output = Matrix;
@@ -415,13 +763,23 @@ fn test_matrix_point_mul() {
     assert_eq!([10.0, 14.0], m * p);
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct LayerColor {
     pub black: String,
     pub grey: String,
     pub white: String,
     pub blue: String,
     pub red: String,
+    /// Also used for `Color::LightYellow`.
+    pub yellow: String,
+    /// Also used for `Color::LightGreen`.
+    pub green: String,
+    pub pink: String,
+    pub cyan: String,
+    pub magenta: String,
+    /// Color of highlighter lines in `Color::Highlight` or one of the colors
+    /// of older firmware, which had a single highlighter color.
+    pub highlight: String,
 }
 
 impl Default for LayerColor {
@@ -432,6 +790,12 @@ impl Default for LayerColor {
             white: "white".to_string(),
             blue: "#0062cc".to_string(),
             red: "#d90707".to_string(),
+            yellow: "#fbe32d".to_string(),
+            green: "#4caf50".to_string(),
+            pink: "#f06ab4".to_string(),
+            cyan: "#2fb4d8".to_string(),
+            magenta: "#b24fc9".to_string(),
+            highlight: "rgb(240, 220, 40)".to_string(),
         }
     }
 }
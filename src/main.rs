@@ -1,6 +1,9 @@
 use anyhow::{Context, Result};
 use clap::{App, Arg};
-use lines_are_rusty::{LayerColor, LinesData};
+use lines_are_rusty::{
+    Error, LayerColor, LinesData, Notebook, Page, ParseOptions, PdfRenderer, PngRenderer,
+    RenderOptions, Renderer, SvgRenderer,
+};
 use std::fs::{metadata, File};
 use std::io::Read;
 use std::io::{self, BufWriter, Write};
@@ -14,7 +17,7 @@ fn main() -> Result<()> {
         .author("Axel Huebl <axel.huebl@plasma.ninja>")
         .arg(
             Arg::with_name("file")
-                .help("The .rm (or .lines) file to read from. If omitted, data is expected to be piped in.")
+                .help("The .rm (or .lines) file to read from. A notebook can be given as its <uuid>.content file, <uuid> directory or as a .zip archive. If omitted, data is expected to be piped in.")
                 .index(1)
                 .empty_values(true)
         )
@@ -23,7 +26,15 @@ fn main() -> Result<()> {
                 .short("o")
                 .long("output")
                 .takes_value(true)
-                .help("The file to save the rendered output to. If omitted, output is written to stdout. Required for PDF.")
+                .help("The file to save the rendered output to. If omitted, output is written to stdout.")
+        )
+        .arg(
+            Arg::with_name("page")
+                .short("p")
+                .long("page")
+                .takes_value(true)
+                .help("The page to render to SVG or PNG, counting from 1")
+                .default_value("1")
         )
         .arg(
             Arg::with_name("auto-crop")
@@ -34,7 +45,7 @@ fn main() -> Result<()> {
             Arg::with_name("custom-colors")
                 .short("c")
                 .long("colors")
-                .help("Which colors to use for the layers. Format: L1-black,L1-gray,L1-white;...;L5-black,L5-gray,L5-white")
+                .help("Which colors to use for the layers. Format: L1-black,L1-gray,L1-white,L1-blue,L1-red;...;L5-black,...,L5-red. Each layer can add yellow, green, pink, cyan, magenta and highlight after red.")
                 .default_value("")
         )
         .arg(
@@ -43,13 +54,26 @@ fn main() -> Result<()> {
                 .long("to")
                 .takes_value(true)
                 .help("Output type. If present, overrides the type determined by the output file extension. Defaults to svg.")
-                .possible_values(&["svg", "pdf"])
+                .possible_values(&["svg", "pdf", "png"])
         )
         .arg(
             Arg::with_name("template")
                 .long("template")
                 .takes_value(true)
-                .help("Page template name")
+                .help("Page template name. Overrides the templates of notebook pages.")
+        )
+        .arg(
+            Arg::with_name("dpi")
+                .long("dpi")
+                .takes_value(true)
+                .help("Resolution of PNG output. The default matches the screen of the tablet.")
+                .default_value("226")
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .takes_value(true)
+                .help("Background color of PNG output, e.g. white or #ffffff. If omitted, the background is transparent.")
         )
         .arg(
             Arg::with_name("distance-threshold")
@@ -58,6 +82,30 @@ fn main() -> Result<()> {
                 .help("Threshold of distance between points, lower values produce higher fidelity renderings at the cost of file sizes")
                 .default_value("2.0")
         )
+        .arg(
+            Arg::with_name("simplify")
+                .long("simplify")
+                .takes_value(true)
+                .value_name("tolerance")
+                .help("Leave out points of lines that deviate at most this far from the simplified line, e.g. 0.5. Unlike the distance threshold, this keeps corners.")
+        )
+        .arg(
+            Arg::with_name("smooth")
+                .long("smooth")
+                .takes_value(true)
+                .value_name("tolerance")
+                .help("Draw lines as smooth curves that deviate at most this far from the points, e.g. 0.5. Applies to SVG and PDF output.")
+        )
+        .arg(
+            Arg::with_name("hidden-layers")
+                .long("hidden-layers")
+                .help("Also render layers that are hidden on the tablet")
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .help("Draw lines with unknown brushes or colors like fineliner lines in black instead of failing")
+        )
         .arg(
             Arg::with_name("debug-dump")
             .short("d")
@@ -75,6 +123,7 @@ fn main() -> Result<()> {
         Some(output_type_string) => match output_type_string.to_lowercase().as_ref() {
             "svg" => OutputType::Svg,
             "pdf" => OutputType::Pdf,
+            "png" => OutputType::Png,
             _ => {
                 eprintln!("Unsupported output file extension {}", output_type_string);
                 exit(1);
@@ -88,22 +137,33 @@ fn main() -> Result<()> {
 
     let layer_colors = colors
         .split(';')
+        .filter(|layer| !layer.is_empty())
         .map(|layer| {
             let c = layer.split(',').collect::<Vec<&str>>();
-            if c.len() != 5 {
+            if c.len() != 5 && c.len() != 11 {
                 eprintln!(
-                    "Expected 5 colors per layer (black, grey, white, blue, red). Found: {}",
+                    "Expected 5 colors per layer (black, grey, white, blue, red), optionally followed by 6 more (yellow, green, pink, cyan, magenta, highlight). Found: {}",
                     layer
                 );
                 exit(1);
             }
-            LayerColor {
+            let mut layer_color = LayerColor {
                 black: c[0].to_string(),
                 grey: c[1].to_string(),
                 white: c[2].to_string(),
                 blue: c[3].to_string(),
                 red: c[4].to_string(),
+                ..Default::default()
+            };
+            if let [yellow, green, pink, cyan, magenta, highlight] = c[5..] {
+                layer_color.yellow = yellow.to_string();
+                layer_color.green = green.to_string();
+                layer_color.pink = pink.to_string();
+                layer_color.cyan = cyan.to_string();
+                layer_color.magenta = magenta.to_string();
+                layer_color.highlight = highlight.to_string();
             }
+            layer_color
         })
         .collect();
 
@@ -113,21 +173,66 @@ fn main() -> Result<()> {
         .parse()
         .expect("Distance threshold not a valid f32");
 
+    let simplify_tolerance: Option<f32> = matches.value_of("simplify").map(|tolerance| {
+        tolerance
+            .parse()
+            .expect("Simplify tolerance not a valid f32")
+    });
+
+    let curve_tolerance: Option<f32> = matches
+        .value_of("smooth")
+        .map(|tolerance| tolerance.parse().expect("Curve tolerance not a valid f32"));
+
     let template: Option<&str> = matches.value_of("template");
 
+    let dpi: f32 = matches
+        .value_of("dpi")
+        .expect("Failed to read dpi")
+        .parse()
+        .expect("DPI not a valid f32");
+
+    let background: Option<&str> = matches.value_of("background");
+
+    let page: usize = matches
+        .value_of("page")
+        .expect("Failed to read page")
+        .parse()
+        .expect("Page not a valid number");
+
+    let hidden_layers = matches.is_present("hidden-layers");
+
+    let parse_options = ParseOptions::new().lenient(matches.is_present("lenient"));
+
     let debug_dump = matches.is_present("debug-dump");
     if debug_dump && (output_type != OutputType::Svg) {
         eprintln!("Warning: debug-dump only has an effect when writing SVG output");
     }
 
+    let mut render_options = RenderOptions::new()
+        .auto_crop(auto_crop)
+        .layer_colors(layer_colors)
+        .distance_threshold(distance_threshold)
+        .dpi(dpi)
+        .hidden_layers(hidden_layers)
+        .debug_dump(debug_dump);
+    if let Some(template) = template {
+        render_options = render_options.template(template);
+    }
+    if let Some(simplify_tolerance) = simplify_tolerance {
+        render_options = render_options.simplify_tolerance(simplify_tolerance);
+    }
+    if let Some(curve_tolerance) = curve_tolerance {
+        render_options = render_options.curve_tolerance(curve_tolerance);
+    }
+    if let Some(background) = background {
+        render_options = render_options.background(background);
+    }
+
     let options = Options {
         output_type,
-        output_filename,
-        layer_colors,
-        auto_crop,
-        distance_threshold,
-        template,
-        debug_dump,
+        render_options,
+        parse_options,
+        page,
     };
 
     let mut output = BufWriter::new(match output_filename {
@@ -140,11 +245,21 @@ fn main() -> Result<()> {
     match matches.value_of("file") {
         None => process_single_file(&mut io::stdin(), &mut output, options)?,
         Some(filename) => {
+            let path = Path::new(filename);
             let metadata =
-                metadata(filename).context(format!("Can't access input file {}", filename))?;
-            if metadata.is_dir() {
-                println!("Can't process directories yet");
-                exit(1);
+                metadata(path).context(format!("Can't access input file {}", filename))?;
+            if metadata.is_dir() || path.extension() == Some("content".as_ref()) {
+                let notebook = Notebook::open_with_options(path, &options.parse_options)
+                    .context(format!("Can't read notebook {}", filename))?;
+                print_warnings(&notebook.warnings);
+                process_pages(&notebook.pages, &mut output, options)?;
+            } else if path.extension() == Some("zip".as_ref()) {
+                let input =
+                    File::open(path).context(format!("Can't open input file {}", filename))?;
+                let notebook = Notebook::from_zip_with_options(input, &options.parse_options)
+                    .context(format!("Can't read notebook archive {}", filename))?;
+                print_warnings(&notebook.warnings);
+                process_pages(&notebook.pages, &mut output, options)?;
             } else {
                 let mut input =
                     File::open(filename).context(format!("Can't open input file {}", filename))?;
@@ -163,43 +278,55 @@ fn process_single_file(
     output: &mut dyn Write,
     opts: Options,
 ) -> Result<()> {
-    let lines_data = LinesData::parse(&mut input).context("Failed to parse lines data")?;
-
-    match opts.output_type {
-        OutputType::Svg => lines_are_rusty::render_svg(
-            output,
-            &lines_data.pages[0],
-            opts.auto_crop,
-            &opts.layer_colors,
-            opts.distance_threshold,
-            opts.template,
-            opts.debug_dump,
-        )
-        .context("failed to write SVG")?,
-        OutputType::Pdf => {
-            // Alas, the pdf-canvas crate insists on writing to a File instead of a Write
-            let pdf_filename = opts
-                .output_filename
-                .context("Output file needed for PDF output")?;
-            lines_are_rusty::render_pdf(pdf_filename, &lines_data.pages)
-                .context("failed to write pdf")?
-        }
+    let lines_data = LinesData::parse_with_options(&mut input, &opts.parse_options)
+        .context("Failed to parse lines data")?;
+    print_warnings(&lines_data.warnings);
+    process_pages(&lines_data.pages, output, opts)
+}
+
+fn print_warnings(warnings: &[Error]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
     }
-    Ok(())
+}
+
+fn process_pages(pages: &[Page], output: &mut dyn Write, opts: Options) -> Result<()> {
+    if pages.is_empty() {
+        eprintln!("Nothing to render, the input has no pages");
+        exit(1);
+    }
+
+    // SVG and PNG documents hold a single page.
+    let pages = match opts.output_type {
+        OutputType::Pdf => pages,
+        OutputType::Svg | OutputType::Png => {
+            let index = opts.page.wrapping_sub(1);
+            pages
+                .get(index..=index)
+                .context(format!("Page {} does not exist", opts.page))?
+        }
+    };
+
+    let render_options = &opts.render_options;
+    let mut renderer: Box<dyn Renderer> = match opts.output_type {
+        OutputType::Svg => Box::new(SvgRenderer::new(output, render_options)),
+        OutputType::Pdf => Box::new(PdfRenderer::new(output, render_options)),
+        OutputType::Png => Box::new(PngRenderer::new(output, render_options)),
+    };
+    lines_are_rusty::render_pages(renderer.as_mut(), pages)
+        .context(format!("failed to write {:?}", opts.output_type))
 }
 
 #[derive(Debug, PartialEq)]
 enum OutputType {
     Svg,
     Pdf,
+    Png,
 }
 
-struct Options<'a> {
+struct Options {
     output_type: OutputType,
-    output_filename: Option<&'a str>,
-    layer_colors: Vec<LayerColor>,
-    auto_crop: bool,
-    distance_threshold: f32,
-    template: Option<&'a str>,
-    debug_dump: bool,
+    render_options: RenderOptions,
+    parse_options: ParseOptions,
+    page: usize,
 }
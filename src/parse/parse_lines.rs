@@ -1,42 +1,124 @@
 use byteorder::{LittleEndian, ReadBytesExt};
-use std::convert::TryFrom;
 use std::io;
 
+use crate::parse::parse_v6::SceneReader;
 use crate::{BrushType, Color, Error, Layer, Line, LinesData, Page, Point, Result};
 
+/// Options for parsing .rm files and notebooks. Start from
+/// `ParseOptions::new()` and set options with the builder methods.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Keep lines with brushes and colors we do not know as
+    /// `BrushType::Unknown` and `Color::Unknown` and report them as warnings,
+    /// instead of failing with `Error::UnknownBrush` or `Error::UnknownColor`.
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+}
+
 impl LinesData {
     /// Parses data from an .rm or .lines file to `LinesData`.
     /// Possible errors are `io::Error` and `VersionError`,
-    /// Currently, only .rm files of version 3 and 5 are supported.
+    /// Currently, .rm files of version 3, 5 and 6 are supported, as well as
+    /// .lines files of early firmware ("reMarkable lines with selections and
+    /// layers"), which are reported as version 1.
     pub fn parse(file: &mut dyn io::Read) -> Result<LinesData> {
-        let mut buffer = [0; 33];
+        LinesData::parse_with_options(file, &ParseOptions::new())
+    }
+
+    /// Parses data like `LinesData::parse`, see `ParseOptions` for the
+    /// options.
+    pub fn parse_with_options(
+        file: &mut dyn io::Read,
+        options: &ParseOptions,
+    ) -> Result<LinesData> {
+        // All headers take 43 bytes. Newer ones are padded with spaces.
+        let mut buffer = [0; 43];
         file.read_exact(&mut buffer)?;
         let untrimmed_string = String::from_utf8_lossy(&buffer);
         let version_string = untrimmed_string.trim_end();
         let version = match version_string {
-            "reMarkable lines with selections and layers" => {
-                // early version of the format that is not supported
-                return Err(Error::UnsupportedVersion(version_string.to_string()));
-            }
+            "reMarkable lines with selections and layers" => 1,
             "reMarkable .lines file, version=3" => 3,
             "reMarkable .lines file, version=5" => 5,
+            "reMarkable .lines file, version=6" => 6,
             _ => return Err(Error::UnsupportedVersion(version_string.to_string())),
         };
 
-        if version >= 3 {
-            // Newer files have 10 more bytes in the ASCII header. Skip them.
-            file.read_exact(&mut [0; 10])?;
-        }
+        let pages = if version >= 6 {
+            SceneReader { file }.read_pages()?
+        } else {
+            LinesDataReader { file, version }.read_pages()?
+        };
 
-        let mut reader = LinesDataReader { file, version };
+        let mut warnings = unknown_codes(&pages);
+        if !options.lenient && !warnings.is_empty() {
+            return Err(warnings.remove(0));
+        }
 
         Ok(LinesData {
             version,
-            pages: reader.read_pages()?,
+            pages,
+            warnings,
         })
     }
 }
 
+/// Errors for the brushes and colors we do not know, one per code.
+fn unknown_codes(pages: &[Page]) -> Vec<Error> {
+    let mut brush_codes = Vec::new();
+    let mut color_codes = Vec::new();
+    let lines = pages
+        .iter()
+        .flat_map(|page| page.layers.iter())
+        .flat_map(|layer| layer.lines.iter());
+    for line in lines {
+        if let BrushType::Unknown(code) = line.brush_type {
+            if !brush_codes.contains(&code) {
+                brush_codes.push(code);
+            }
+        }
+        if let Color::Unknown(code) = line.color {
+            if !color_codes.contains(&code) {
+                color_codes.push(code);
+            }
+        }
+    }
+    let brush_errors = brush_codes.into_iter().map(Error::UnknownBrush);
+    let color_errors = color_codes.into_iter().map(Error::UnknownColor);
+    brush_errors.chain(color_errors).collect()
+}
+
+/// Width of a point of early firmware, which only stores the base size of
+/// the line: 1.875, 2.0 and 2.125 for thin, medium and thick pens. Newer
+/// firmware stores constant width strokes of these sizes with widths of 2, 4
+/// and 6, highlighters with 30 and erasers with 40. Strokes of brushes that
+/// follow the pressure are as wide at half pressure and get wider with it.
+fn legacy_point_width(brush_type: BrushType, brush_base_size: f32, pressure: f32) -> f32 {
+    let width = (16.0 * (brush_base_size - 1.75)).max(1.0);
+    match brush_type {
+        BrushType::Highlighter => 7.5 * width,
+        BrushType::Eraser => 10.0 * width,
+        BrushType::Fineliner
+        | BrushType::SharpPencil
+        | BrushType::EraseArea
+        | BrushType::EraseAll
+        | BrushType::SelectionBrush
+        | BrushType::Unknown(_) => width,
+        _ => width * (0.5 + pressure),
+    }
+}
+
 pub(crate) struct LinesDataReader<'a> {
     pub file: &'a mut dyn io::Read,
     pub version: i32,
@@ -52,9 +134,14 @@ impl LinesDataReader<'_> {
     }
 
     fn read_line(&mut self) -> Result<Line> {
-        Ok(Line {
-            brush_type: BrushType::try_from(self.read_i32()?)?,
-            color: Color::try_from(self.read_i32()?)?,
+        let brush_code = self.read_i32()?;
+        let color_code = self.read_i32()?;
+        let mut line = Line {
+            brush_type: BrushType::from_code(brush_code),
+            color: Color::from_code(color_code),
+            brush_code: Some(brush_code),
+            color_code: Some(color_code),
+            argb: None,
             unknown_line_attribute: self.read_i32()?,
             brush_base_size: self.read_f32()?, // width
             unknown_line_attribute_2: if self.version >= 5 {
@@ -63,7 +150,14 @@ impl LinesDataReader<'_> {
                 0
             },
             points: self.read_points()?,
-        })
+        };
+        if self.version < 3 {
+            for point in line.points.iter_mut() {
+                point.width =
+                    legacy_point_width(line.brush_type, line.brush_base_size, point.pressure);
+            }
+        }
+        Ok(line)
     }
 
     fn read_points(&mut self) -> Result<Vec<Point>> {
@@ -72,6 +166,9 @@ impl LinesDataReader<'_> {
     }
 
     fn read_point(&mut self) -> Result<Point> {
+        if self.version < 3 {
+            return self.read_legacy_point();
+        }
         Ok(Point {
             x: self.read_f32()?,
             y: self.read_f32()?,
@@ -82,6 +179,22 @@ impl LinesDataReader<'_> {
         })
     }
 
+    /// Early firmware stores x, y, pressure and the pen tilt in x and y
+    /// direction. There is no per-point width, see `legacy_point_width`.
+    fn read_legacy_point(&mut self) -> Result<Point> {
+        let x = self.read_f32()?;
+        let y = self.read_f32()?;
+        let pressure = self.read_f32()?;
+        let _rot_x = self.read_f32()?;
+        let _rot_y = self.read_f32()?;
+        Ok(Point {
+            x,
+            y,
+            pressure,
+            ..Default::default()
+        })
+    }
+
     fn read_lines(&mut self) -> Result<Vec<Line>> {
         let num_lines = self.read_i32()?;
         (0..num_lines).map(|_| self.read_line()).collect()
@@ -93,6 +206,7 @@ impl LinesDataReader<'_> {
             .map(|_| {
                 Ok(Layer {
                     lines: self.read_lines()?,
+                    ..Default::default()
                 })
             })
             .collect()
@@ -110,8 +224,76 @@ impl LinesDataReader<'_> {
             .map(|_| {
                 Ok(Page {
                     layers: self.read_layers()?,
+                    ..Default::default()
                 })
             })
             .collect()
     }
 }
+
+#[test]
+fn test_parse_legacy_pages() {
+    let mut data = b"reMarkable lines with selections and layers".to_vec();
+    fn push_i32(data: &mut Vec<u8>, v: i32) {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    push_i32(&mut data, 2); // pages
+    for page in 0..2 {
+        push_i32(&mut data, 1); // layers
+        push_i32(&mut data, 1); // lines
+                                // A pen on the first page and a fineliner on the second.
+        for attribute in [[2, 0, 0], [4, 0, 0]][page] {
+            push_i32(&mut data, attribute); // brush, color, unknown
+        }
+        data.extend_from_slice(&2.0f32.to_le_bytes()); // brush base size
+        push_i32(&mut data, 1); // points
+        for value in [page as f32, 10.0, [1.0, 0.5][page], 0.0, 0.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let lines_data = LinesData::parse(&mut &data[..]).unwrap();
+    assert_eq!(lines_data.version, 1);
+    assert_eq!(lines_data.pages.len(), 2);
+    let point = &lines_data.pages[1].layers[0].lines[0].points[0];
+    assert_eq!(
+        (point.x, point.y, point.pressure, point.width),
+        (1.0, 10.0, 0.5, 4.0)
+    );
+    // The medium pen is wider at full pressure.
+    let point = &lines_data.pages[0].layers[0].lines[0].points[0];
+    assert_eq!((point.pressure, point.width), (1.0, 6.0));
+}
+
+#[test]
+fn test_parse_unknown_codes() {
+    let mut data = b"reMarkable .lines file, version=5          ".to_vec();
+    for value in [1i32, 2, 99, 42, 0] {
+        data.extend_from_slice(&value.to_le_bytes()); // layers, lines, brush, color, unknown
+    }
+    data.extend_from_slice(&2.0f32.to_le_bytes()); // brush base size
+    for value in [0i32, 0] {
+        data.extend_from_slice(&value.to_le_bytes()); // unknown, points
+    }
+    // A second line with the same codes.
+    let line = data[51..].to_vec();
+    data.extend_from_slice(&line);
+
+    let error = LinesData::parse(&mut &data[..]).unwrap_err();
+    assert!(matches!(error, Error::UnknownBrush(99)));
+
+    let options = ParseOptions::new().lenient(true);
+    let lines_data = LinesData::parse_with_options(&mut &data[..], &options).unwrap();
+    let lines = &lines_data.pages[0].layers[0].lines;
+    assert_eq!(lines.len(), 2);
+    assert!(matches!(lines[0].brush_type, BrushType::Unknown(99)));
+    assert!(matches!(lines[0].color, Color::Unknown(42)));
+    assert_eq!(
+        lines_data
+            .warnings
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        vec!["Unknown brush type: 99", "Unknown color: 42"]
+    );
+}
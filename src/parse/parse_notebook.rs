@@ -0,0 +1,311 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{Error, LinesData, Notebook, Orientation, Page, ParseOptions, Result};
+
+/// The parts of a `<uuid>.content` file we are interested in. Older
+/// firmware lists the page ids in `pages`, newer firmware stores a CRDT
+/// backed page list in `cPages`. The orientation applies to all pages.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    #[serde(default)]
+    pages: Vec<String>,
+    c_pages: Option<CPages>,
+    orientation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CPages {
+    pages: Vec<CPage>,
+}
+
+#[derive(Deserialize)]
+struct CPage {
+    id: String,
+    idx: Option<Value<String>>,
+    template: Option<Value<String>>,
+    deleted: Option<Value<i32>>,
+}
+
+/// A last-writer-wins value as stored in `cPages`. We ignore the timestamp.
+#[derive(Deserialize)]
+struct Value<T> {
+    value: T,
+}
+
+/// The `<page>-metadata.json` file stored next to each page.
+#[derive(Deserialize)]
+struct PageMetadata {
+    layers: Vec<LayerMetadata>,
+}
+
+#[derive(Deserialize)]
+struct LayerMetadata {
+    name: Option<String>,
+    visible: Option<bool>,
+}
+
+/// Page id and, if known, template name of a page.
+type PageEntry = (String, Option<String>);
+
+impl Content {
+    fn page_entries(self) -> Vec<PageEntry> {
+        match self.c_pages {
+            Some(c_pages) => {
+                let mut pages: Vec<CPage> = c_pages
+                    .pages
+                    .into_iter()
+                    .filter(|page| page.deleted.as_ref().is_none_or(|d| d.value == 0))
+                    .collect();
+                // Pages are ordered by their (fractional) index strings.
+                pages.sort_by(|a, b| {
+                    let index = |page: &CPage| page.idx.as_ref().map(|idx| idx.value.clone());
+                    index(a).cmp(&index(b))
+                });
+                pages
+                    .into_iter()
+                    .map(|page| (page.id, page.template.map(|template| template.value)))
+                    .collect()
+            }
+            None => self.pages.into_iter().map(|id| (id, None)).collect(),
+        }
+    }
+}
+
+impl Notebook {
+    /// Loads a notebook stored in the document layout of the tablet's
+    /// `xochitl` directory. `path` is either the `<uuid>.content` file or the
+    /// `<uuid>` directory next to it.
+    pub fn open(path: &Path) -> Result<Notebook> {
+        Notebook::open_with_options(path, &ParseOptions::new())
+    }
+
+    /// Loads a notebook like `Notebook::open`, see `ParseOptions` for the
+    /// options.
+    pub fn open_with_options(path: &Path, options: &ParseOptions) -> Result<Notebook> {
+        let uuid = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?
+            .to_string();
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Notebook::read(
+            uuid,
+            options,
+            &mut |name| match fs::read(directory.join(name)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+        )
+    }
+
+    /// Loads a notebook from a zip archive holding the `<uuid>.content` file
+    /// and the other files of the notebook, as created by exports and backups
+    /// of the tablet. The files may be nested in a directory of the archive.
+    /// Archives with more than one `.content` file are rejected with
+    /// `Error::MultipleContents`.
+    pub fn from_zip<R: io::Read + io::Seek>(reader: R) -> Result<Notebook> {
+        Notebook::from_zip_with_options(reader, &ParseOptions::new())
+    }
+
+    /// Loads a notebook like `Notebook::from_zip`, see `ParseOptions` for
+    /// the options.
+    pub fn from_zip_with_options<R: io::Read + io::Seek>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Notebook> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let content_names: Vec<&str> = archive
+            .file_names()
+            .filter(|name| name.ends_with(".content"))
+            .collect();
+        let content_name = match content_names[..] {
+            [content_name] => content_name.to_string(),
+            [] => return Err(Error::MissingContent),
+            _ => return Err(Error::MultipleContents),
+        };
+        let (prefix, uuid) = match content_name.rfind('/') {
+            Some(slash) => content_name.split_at(slash + 1),
+            None => ("", content_name.as_str()),
+        };
+        let uuid = uuid.trim_end_matches(".content").to_string();
+
+        Notebook::read(uuid, options, &mut |name| {
+            let mut file = match archive.by_name(&format!("{}{}", prefix, name)) {
+                Ok(file) => file,
+                Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let mut data = Vec::new();
+            io::Read::read_to_end(&mut file, &mut data)?;
+            Ok(Some(data))
+        })
+    }
+
+    /// Reads a notebook, fetching its files by their path relative to the
+    /// directory holding `<uuid>.content`. `read_file` returns `None` for
+    /// files that do not exist.
+    pub(crate) fn read(
+        uuid: String,
+        options: &ParseOptions,
+        read_file: &mut dyn FnMut(&str) -> Result<Option<Vec<u8>>>,
+    ) -> Result<Notebook> {
+        let content: Content = match read_file(&format!("{}.content", uuid))? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => return Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        };
+        let orientation = match content.orientation.as_deref() {
+            Some("landscape") => Orientation::Landscape,
+            _ => Orientation::Portrait,
+        };
+
+        // One template name per line, in page order
+        let pagedata = read_file(&format!("{}.pagedata", uuid))?
+            .map(|data| String::from_utf8_lossy(&data).into_owned())
+            .unwrap_or_default();
+        let mut page_templates = pagedata.lines().map(str::to_string);
+
+        let mut pages = Vec::new();
+        let mut warnings = Vec::new();
+        for (index, (page_id, template)) in content.page_entries().into_iter().enumerate() {
+            let template = template.or(page_templates.next());
+
+            // Newer firmware names page files by page id, older firmware by
+            // page index. Pages without any strokes have no file.
+            let mut read_page_file = |suffix: &str| -> Result<Option<Vec<u8>>> {
+                match read_file(&format!("{}/{}{}", uuid, page_id, suffix))? {
+                    Some(data) => Ok(Some(data)),
+                    None => read_file(&format!("{}/{}{}", uuid, index, suffix)),
+                }
+            };
+            let mut page = match read_page_file(".rm")? {
+                Some(data) => {
+                    let lines_data = LinesData::parse_with_options(&mut &data[..], options)?;
+                    warnings.extend(lines_data.warnings);
+                    lines_data.pages.into_iter().next().unwrap_or_default()
+                }
+                None => Page::default(),
+            };
+            page.template = template.filter(|template| !template.is_empty());
+            page.orientation = orientation;
+
+            if let Some(data) = read_page_file("-metadata.json")? {
+                let metadata: PageMetadata = serde_json::from_slice(&data)?;
+                for (layer, layer_metadata) in page.layers.iter_mut().zip(metadata.layers) {
+                    layer.name = layer_metadata.name.or(layer.name.take());
+                    layer.hidden = layer_metadata.visible.map_or(layer.hidden, |v| !v);
+                }
+            }
+            pages.push(page);
+        }
+
+        Ok(Notebook {
+            uuid,
+            pages,
+            warnings,
+        })
+    }
+}
+
+#[test]
+fn test_read_notebook() {
+    let uuid = "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3";
+    let page = |num_layers: i32| {
+        let mut data = b"reMarkable .lines file, version=5          ".to_vec();
+        data.extend_from_slice(&num_layers.to_le_bytes());
+        for _ in 0..num_layers {
+            data.extend_from_slice(&0i32.to_le_bytes()); // lines
+        }
+        Some(data)
+    };
+
+    let notebook = Notebook::read(uuid.to_string(), &ParseOptions::new(), &mut |name| {
+        Ok(match name {
+            "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3.content" => Some(
+                br#"{"pages": ["first", "second", "third"], "orientation": "landscape"}"#.to_vec(),
+            ),
+            "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3.pagedata" => {
+                Some(b"P Grid medium\nBlank\nP Lines small\n".to_vec())
+            }
+            "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3/0.rm" => page(1),
+            "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3/third.rm" => page(3),
+            "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3/third-metadata.json" => Some(
+                br#"{"layers": [{"name": "Sketch"}, {"name": "Notes", "visible": false}]}"#
+                    .to_vec(),
+            ),
+            _ => None,
+        })
+    })
+    .unwrap();
+
+    assert_eq!(notebook.uuid, uuid);
+    assert_eq!(
+        notebook
+            .pages
+            .iter()
+            .map(|page| page.template.as_deref())
+            .collect::<Vec<_>>(),
+        vec![Some("P Grid medium"), Some("Blank"), Some("P Lines small")]
+    );
+    assert_eq!(
+        notebook
+            .pages
+            .iter()
+            .map(|page| page.layers.len())
+            .collect::<Vec<_>>(),
+        vec![1, 0, 3]
+    );
+    assert!(notebook
+        .pages
+        .iter()
+        .all(|page| page.orientation == Orientation::Landscape));
+    let layers = &notebook.pages[2].layers;
+    assert_eq!(layers[0].name.as_deref(), Some("Sketch"));
+    assert_eq!(layers[1].name.as_deref(), Some("Notes"));
+    assert_eq!(layers[2].name, None);
+    assert_eq!(
+        layers.iter().map(|layer| layer.hidden).collect::<Vec<_>>(),
+        vec![false, true, false]
+    );
+}
+
+#[test]
+fn test_notebook_from_zip() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_rm_notebook.zip");
+    let notebook = Notebook::from_zip(fs::File::open(path).unwrap()).unwrap();
+
+    assert_eq!(notebook.uuid, "b46bbfdd-aeb0-42d7-9c5d-7443132bdfe3");
+    assert_eq!(notebook.pages.len(), 1);
+    let page = &notebook.pages[0];
+    assert_eq!(page.template.as_deref(), Some("P Grid medium"));
+    assert_eq!(page.orientation, Orientation::Portrait);
+    assert_eq!(page.layers.len(), 1);
+    assert_eq!(page.layers[0].lines.len(), 445);
+    assert_eq!(page.layers[0].name.as_deref(), Some("Layer 1"));
+}
+
+#[test]
+fn test_notebook_from_zip_content_files() {
+    use std::io::Write;
+
+    let zip = |names: &[&str]| {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in names {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(b"{}").unwrap();
+        }
+        writer.finish().unwrap()
+    };
+
+    let error = Notebook::from_zip(zip(&["a.pdf"])).unwrap_err();
+    assert!(matches!(error, Error::MissingContent));
+    let error = Notebook::from_zip(zip(&["a.content", "b/b.content"])).unwrap_err();
+    assert!(matches!(error, Error::MultipleContents));
+}
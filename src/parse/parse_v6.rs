@@ -0,0 +1,555 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+
+use crate::{BrushType, Color, Error, Layer, Line, Page, Point, Result};
+
+// Block types of the version 6 format. Blocks of other types (author ids,
+// page info, text, ...) carry nothing we can put into the model and are
+// skipped. The scene tree blocks are redundant for us, as group items
+// already link each group to its parent.
+const BLOCK_TREE_NODE: u8 = 0x02;
+const BLOCK_SCENE_GROUP_ITEM: u8 = 0x04;
+const BLOCK_SCENE_LINE_ITEM: u8 = 0x05;
+
+// Tag types. Every value in a block is prefixed with a varuint tag that
+// combines an index (upper bits) with one of these types (lower 4 bits).
+const TAG_BYTE1: u8 = 0x1;
+const TAG_BYTE4: u8 = 0x4;
+const TAG_BYTE8: u8 = 0x8;
+const TAG_LENGTH4: u8 = 0xC;
+const TAG_ID: u8 = 0xF;
+
+// Scene item types, stored as the first byte of an item's value subblock.
+const ITEM_GROUP: u8 = 0x02;
+const ITEM_LINE: u8 = 0x03;
+
+/// The root group of the scene tree. Its direct child groups are the layers.
+const ROOT_ID: CrdtId = CrdtId(0, 1);
+
+/// Version 6 files store x coordinates relative to the horizontal page center.
+/// Versions 3 and 5 (and thus our model) have the origin at the top left.
+const X_OFFSET: f32 = 702.0;
+
+/// Identifier of a node or item in the CRDT based scene tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+struct CrdtId(u8, u64);
+
+/// A scene item as stored in a CRDT sequence. Deleted items (tombstones)
+/// have no value, but still take part in ordering their siblings.
+struct SceneItem {
+    item_id: CrdtId,
+    left_id: CrdtId,
+    right_id: CrdtId,
+    value: Option<SceneItemValue>,
+}
+
+/// Name and visibility of a group in the scene tree.
+struct TreeNode {
+    label: String,
+    visible: bool,
+}
+
+enum SceneItemValue {
+    Group(CrdtId),
+    Line(Line),
+}
+
+/// Reads the tagged values of a single block.
+struct TaggedBlockReader<'a> {
+    data: Cursor<&'a [u8]>,
+    version: u8,
+}
+
+impl<'a> TaggedBlockReader<'a> {
+    fn new(data: &'a [u8], version: u8) -> Self {
+        TaggedBlockReader {
+            data: Cursor::new(data),
+            version,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.get_ref().len() - self.data.position() as usize
+    }
+
+    fn read_varuint(&mut self) -> Result<u64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            if shift >= 64 {
+                return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+            }
+            let byte = self.data.read_u8()?;
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Returns index and type of the next tag without consuming it.
+    fn peek_tag(&mut self) -> Option<(u64, u8)> {
+        let position = self.data.position();
+        let tag = self.read_varuint().ok();
+        self.data.set_position(position);
+        tag.map(|tag| (tag >> 4, (tag & 0xf) as u8))
+    }
+
+    fn has_tag(&mut self, index: u64, tag_type: u8) -> bool {
+        self.peek_tag() == Some((index, tag_type))
+    }
+
+    fn expect_tag(&mut self, index: u64, tag_type: u8) -> Result<()> {
+        let offset = self.data.position() as usize;
+        if self.has_tag(index, tag_type) {
+            self.read_varuint()?;
+            Ok(())
+        } else {
+            Err(Error::UnexpectedTag {
+                offset,
+                index,
+                tag_type,
+            })
+        }
+    }
+
+    fn read_id(&mut self, index: u64) -> Result<CrdtId> {
+        self.expect_tag(index, TAG_ID)?;
+        Ok(CrdtId(self.data.read_u8()?, self.read_varuint()?))
+    }
+
+    fn read_bool(&mut self, index: u64) -> Result<bool> {
+        self.expect_tag(index, TAG_BYTE1)?;
+        Ok(self.data.read_u8()? != 0)
+    }
+
+    fn read_u32(&mut self, index: u64) -> Result<u32> {
+        self.expect_tag(index, TAG_BYTE4)?;
+        Ok(self.data.read_u32::<LittleEndian>()?)
+    }
+
+    fn read_f32(&mut self, index: u64) -> Result<f32> {
+        self.expect_tag(index, TAG_BYTE4)?;
+        Ok(self.data.read_f32::<LittleEndian>()?)
+    }
+
+    fn read_f64(&mut self, index: u64) -> Result<f64> {
+        self.expect_tag(index, TAG_BYTE8)?;
+        Ok(self.data.read_f64::<LittleEndian>()?)
+    }
+
+    /// Reads a length-prefixed subblock and returns a reader for its content.
+    fn read_subblock(&mut self, index: u64) -> Result<TaggedBlockReader<'a>> {
+        self.expect_tag(index, TAG_LENGTH4)?;
+        let length = self.data.read_u32::<LittleEndian>()? as usize;
+        if length > self.remaining() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let start = self.data.position() as usize;
+        let data: &'a [u8] = self.data.get_ref();
+        self.data.set_position((start + length) as u64);
+        Ok(TaggedBlockReader::new(
+            &data[start..start + length],
+            self.version,
+        ))
+    }
+
+    fn read_string(&mut self, index: u64) -> Result<String> {
+        let mut block = self.read_subblock(index)?;
+        let length = block.read_varuint()? as usize;
+        let _is_ascii = block.data.read_u8()?;
+        if length > block.remaining() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let mut bytes = vec![0; length];
+        io::Read::read_exact(&mut block.data, &mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Reads a group node of the scene tree. Label and visibility are
+    /// last-writer-wins values, of which we ignore the timestamps.
+    fn read_tree_node(&mut self) -> Result<(CrdtId, TreeNode)> {
+        let node_id = self.read_id(1)?;
+        let mut label = self.read_subblock(2)?;
+        let _timestamp = label.read_id(1)?;
+        let label = label.read_string(2)?;
+        let mut visible = self.read_subblock(3)?;
+        let _timestamp = visible.read_id(1)?;
+        let visible = visible.read_bool(2)?;
+        Ok((node_id, TreeNode { label, visible }))
+    }
+
+    /// Reads the header common to all scene items and, unless the item is
+    /// deleted, its value.
+    fn read_scene_item(&mut self, item_type: u8) -> Result<(CrdtId, SceneItem)> {
+        let parent_id = self.read_id(1)?;
+        let item_id = self.read_id(2)?;
+        let left_id = self.read_id(3)?;
+        let right_id = self.read_id(4)?;
+        let _deleted_length = self.read_u32(5)?;
+
+        let value = if self.has_tag(6, TAG_LENGTH4) {
+            let mut value_block = self.read_subblock(6)?;
+            let found_type = value_block.data.read_u8()?;
+            if found_type != item_type {
+                return Err(Error::UnexpectedItemType(found_type));
+            }
+            Some(match item_type {
+                ITEM_GROUP => SceneItemValue::Group(value_block.read_id(2)?),
+                _ => SceneItemValue::Line(value_block.read_line()?),
+            })
+        } else {
+            None
+        };
+
+        Ok((
+            parent_id,
+            SceneItem {
+                item_id,
+                left_id,
+                right_id,
+                value,
+            },
+        ))
+    }
+
+    fn read_line(&mut self) -> Result<Line> {
+        let brush_code = self.read_u32(1)? as i32;
+        let color_code = self.read_u32(2)? as i32;
+        let brush_base_size = self.read_f64(3)? as f32;
+        let _starting_length = self.read_f32(4)?;
+        let points = self.read_subblock(5)?.read_points()?;
+        // Newer versions follow up with a timestamp, the id of the move that
+        // placed the line and the exact color.
+        for index in [6, 7] {
+            if self.has_tag(index, TAG_ID) {
+                self.read_id(index)?;
+            }
+        }
+        let argb = if self.has_tag(8, TAG_BYTE4) {
+            Some(self.read_u32(8)?)
+        } else {
+            None
+        };
+        Ok(Line {
+            brush_type: BrushType::from_code(brush_code),
+            color: Color::from_code(color_code),
+            brush_code: Some(brush_code),
+            color_code: Some(color_code),
+            argb,
+            brush_base_size,
+            points,
+            ..Default::default()
+        })
+    }
+
+    /// Reads points until the end of the (sub)block. Blocks of version 1
+    /// store the same six floats per point as the older formats, version 2
+    /// packs them into 14 bytes.
+    fn read_points(&mut self) -> Result<Vec<Point>> {
+        let point_size = if self.version >= 2 { 14 } else { 24 };
+        (0..self.remaining() / point_size)
+            .map(|_| self.read_point())
+            .collect()
+    }
+
+    fn read_point(&mut self) -> Result<Point> {
+        let x = self.data.read_f32::<LittleEndian>()? + X_OFFSET;
+        let y = self.data.read_f32::<LittleEndian>()?;
+        if self.version >= 2 {
+            let speed = self.data.read_u16::<LittleEndian>()?;
+            let width = self.data.read_u16::<LittleEndian>()?;
+            let direction = self.data.read_u8()?;
+            let pressure = self.data.read_u8()?;
+            Ok(Point {
+                x,
+                y,
+                speed: f32::from(speed) / 4.0,
+                direction: f32::from(direction) / 255.0 * std::f32::consts::TAU,
+                width: f32::from(width) / 4.0,
+                pressure: f32::from(pressure) / 255.0,
+            })
+        } else {
+            Ok(Point {
+                x,
+                y,
+                speed: self.data.read_f32::<LittleEndian>()?,
+                direction: self.data.read_f32::<LittleEndian>()?,
+                width: self.data.read_f32::<LittleEndian>()?,
+                pressure: self.data.read_f32::<LittleEndian>()?,
+            })
+        }
+    }
+}
+
+pub(crate) struct SceneReader<'a> {
+    pub file: &'a mut dyn io::Read,
+}
+
+impl SceneReader<'_> {
+    /// Reads the sequence of blocks following the header. Version 6 files
+    /// contain a single page whose layers are the groups directly below the
+    /// root of the scene tree.
+    pub fn read_pages(&mut self) -> Result<Vec<Page>> {
+        let mut data = Vec::new();
+        self.file.read_to_end(&mut data)?;
+
+        let mut children: HashMap<CrdtId, Vec<SceneItem>> = HashMap::new();
+        let mut tree_nodes = HashMap::new();
+
+        let mut file = Cursor::new(&data[..]);
+        while (file.position() as usize) < data.len() {
+            let length = file.read_u32::<LittleEndian>()? as usize;
+            let _unknown = file.read_u8()?;
+            let _min_version = file.read_u8()?;
+            let version = file.read_u8()?;
+            let block_type = file.read_u8()?;
+
+            let start = file.position() as usize;
+            let block = data
+                .get(start..start + length)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            file.set_position((start + length) as u64);
+
+            let mut reader = TaggedBlockReader::new(block, version);
+            let item_type = match block_type {
+                BLOCK_TREE_NODE => {
+                    let (node_id, node) = reader.read_tree_node()?;
+                    tree_nodes.insert(node_id, node);
+                    continue;
+                }
+                BLOCK_SCENE_GROUP_ITEM => ITEM_GROUP,
+                BLOCK_SCENE_LINE_ITEM => ITEM_LINE,
+                _ => continue,
+            };
+            let (parent_id, item) = reader.read_scene_item(item_type)?;
+            children.entry(parent_id).or_default().push(item);
+        }
+
+        let mut children: HashMap<CrdtId, Vec<SceneItemValue>> = children
+            .into_iter()
+            .map(|(parent_id, items)| (parent_id, order_items(items)))
+            .collect();
+
+        // Lines placed directly on the root end up in an implicit first layer.
+        let mut layers = Vec::new();
+        let mut root_lines = Vec::new();
+        for item in children.remove(&ROOT_ID).unwrap_or_default() {
+            match item {
+                SceneItemValue::Group(node_id) => {
+                    let mut lines = Vec::new();
+                    collect_lines(node_id, &mut children, &mut lines);
+                    let node = tree_nodes.remove(&node_id);
+                    layers.push(Layer {
+                        lines,
+                        name: node.as_ref().map(|node| node.label.clone()),
+                        hidden: node.is_some_and(|node| !node.visible),
+                    });
+                }
+                SceneItemValue::Line(line) => root_lines.push(line),
+            }
+        }
+        if !root_lines.is_empty() {
+            layers.insert(
+                0,
+                Layer {
+                    lines: root_lines,
+                    ..Default::default()
+                },
+            );
+        }
+
+        Ok(vec![Page {
+            layers,
+            ..Default::default()
+        }])
+    }
+}
+
+/// Appends the lines of a group and, in sequence order, of its subgroups.
+fn collect_lines(
+    node_id: CrdtId,
+    children: &mut HashMap<CrdtId, Vec<SceneItemValue>>,
+    lines: &mut Vec<Line>,
+) {
+    for item in children.remove(&node_id).unwrap_or_default() {
+        match item {
+            SceneItemValue::Group(child_id) => collect_lines(child_id, children, lines),
+            SceneItemValue::Line(line) => lines.push(line),
+        }
+    }
+}
+
+/// Puts the items of a CRDT sequence into document order. Each item is
+/// inserted right after its left neighbour (or before its right neighbour)
+/// at the time it was written. This resolves sequential edits, which is what
+/// a single tablet produces; concurrent edits are merged in file order.
+fn order_items(items: Vec<SceneItem>) -> Vec<SceneItemValue> {
+    let mut ordered: Vec<SceneItem> = Vec::with_capacity(items.len());
+    for item in items {
+        let position = if item.left_id == CrdtId::default() {
+            Some(0)
+        } else {
+            ordered
+                .iter()
+                .position(|other| other.item_id == item.left_id)
+                .map(|i| i + 1)
+        }
+        .or_else(|| {
+            ordered
+                .iter()
+                .position(|other| other.item_id == item.right_id)
+        })
+        .unwrap_or(ordered.len());
+        ordered.insert(position, item);
+    }
+    ordered.into_iter().filter_map(|item| item.value).collect()
+}
+
+#[test]
+fn test_read_v6() {
+    fn tag(data: &mut Vec<u8>, index: u8, tag_type: u8) {
+        // Tags from index 8 on take two bytes as varuint.
+        let tag = u16::from(index) << 4 | u16::from(tag_type);
+        if tag < 0x80 {
+            data.push(tag as u8);
+        } else {
+            data.extend_from_slice(&[tag as u8 | 0x80, (tag >> 7) as u8]);
+        }
+    }
+    fn id(data: &mut Vec<u8>, index: u8, part1: u8, part2: u8) {
+        tag(data, index, TAG_ID);
+        data.extend_from_slice(&[part1, part2]);
+    }
+    fn subblock(data: &mut Vec<u8>, index: u8, content: &[u8]) {
+        tag(data, index, TAG_LENGTH4);
+        data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        data.extend_from_slice(content);
+    }
+    fn block(data: &mut Vec<u8>, block_type: u8, version: u8, content: &[u8]) {
+        data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0, 1, version, block_type]);
+        data.extend_from_slice(content);
+    }
+    fn scene_item(item_id: u8, parent_id: u8, right_id: u8, value: &[u8]) -> Vec<u8> {
+        let mut item = Vec::new();
+        id(&mut item, 1, 0, parent_id);
+        id(&mut item, 2, 1, item_id);
+        id(&mut item, 3, 0, 0);
+        id(&mut item, 4, if right_id == 0 { 0 } else { 1 }, right_id);
+        tag(&mut item, 5, TAG_BYTE4);
+        item.extend_from_slice(&0u32.to_le_bytes());
+        subblock(&mut item, 6, value);
+        item
+    }
+    fn line_value(size: f64, x: f32, argb: Option<u32>) -> Vec<u8> {
+        let mut value = vec![ITEM_LINE];
+        tag(&mut value, 1, TAG_BYTE4);
+        value.extend_from_slice(&17u32.to_le_bytes());
+        tag(&mut value, 2, TAG_BYTE4);
+        let color: u32 = if argb.is_some() { 9 } else { 0 };
+        value.extend_from_slice(&color.to_le_bytes());
+        tag(&mut value, 3, TAG_BYTE8);
+        value.extend_from_slice(&size.to_le_bytes());
+        tag(&mut value, 4, TAG_BYTE4);
+        value.extend_from_slice(&0f32.to_le_bytes());
+        let mut points = Vec::new();
+        for (x, y) in [(x, 10.0f32), (x + 5.0, 20.0)] {
+            points.extend_from_slice(&x.to_le_bytes());
+            points.extend_from_slice(&y.to_le_bytes());
+            points.extend_from_slice(&8u16.to_le_bytes()); // speed
+            points.extend_from_slice(&12u16.to_le_bytes()); // width
+            points.extend_from_slice(&[0, 255]); // direction, pressure
+        }
+        subblock(&mut value, 5, &points);
+        id(&mut value, 6, 1, 99);
+        if let Some(argb) = argb {
+            id(&mut value, 7, 1, 98);
+            tag(&mut value, 8, TAG_BYTE4);
+            value.extend_from_slice(&argb.to_le_bytes());
+        }
+        value
+    }
+
+    let mut data = b"reMarkable .lines file, version=6          ".to_vec();
+    let mut tree_node = Vec::new();
+    id(&mut tree_node, 1, 0, 11);
+    let mut label = Vec::new();
+    id(&mut label, 1, 1, 1);
+    subblock(&mut label, 2, b"\x05\x01Notes");
+    subblock(&mut tree_node, 2, &label);
+    let mut visible = Vec::new();
+    id(&mut visible, 1, 1, 2);
+    tag(&mut visible, 2, TAG_BYTE1);
+    visible.push(0);
+    subblock(&mut tree_node, 3, &visible);
+    block(&mut data, BLOCK_TREE_NODE, 1, &tree_node);
+
+    let mut group_value = vec![ITEM_GROUP];
+    id(&mut group_value, 2, 0, 11);
+    block(
+        &mut data,
+        BLOCK_SCENE_GROUP_ITEM,
+        1,
+        &scene_item(10, 1, 0, &group_value),
+    );
+    block(
+        &mut data,
+        BLOCK_SCENE_LINE_ITEM,
+        2,
+        &scene_item(20, 11, 0, &line_value(2.0, -100.0, None)),
+    );
+    // Inserted in front of the first line.
+    block(
+        &mut data,
+        BLOCK_SCENE_LINE_ITEM,
+        2,
+        &scene_item(21, 11, 20, &line_value(1.0, 100.0, Some(0xff_a1_d8_7d))),
+    );
+
+    let lines_data = crate::LinesData::parse(&mut &data[..]).unwrap();
+    assert_eq!(lines_data.version, 6);
+    assert_eq!(lines_data.pages.len(), 1);
+    let layers = &lines_data.pages[0].layers;
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers[0].name.as_deref(), Some("Notes"));
+    assert!(layers[0].hidden);
+    let lines = &layers[0].lines;
+    assert_eq!(
+        lines.iter().map(|l| l.brush_base_size).collect::<Vec<_>>(),
+        vec![1.0, 2.0]
+    );
+    assert_eq!(lines[0].color, crate::Color::Highlight);
+    assert_eq!(lines[0].argb, Some(0xff_a1_d8_7d));
+    assert_eq!(lines[1].argb, None);
+    let point = &lines[1].points[1];
+    assert_eq!((point.x, point.y), (607.0, 20.0));
+    assert_eq!((point.speed, point.width, point.pressure), (2.0, 3.0, 1.0));
+
+    // Malformed files fail instead of allocating huge strings or
+    // overflowing the varuint shift.
+    let header = b"reMarkable .lines file, version=6          ";
+    let mut huge_label = Vec::new();
+    id(&mut huge_label, 1, 0, 11);
+    let mut label = Vec::new();
+    id(&mut label, 1, 1, 1);
+    subblock(
+        &mut label,
+        2,
+        &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 1],
+    );
+    subblock(&mut huge_label, 2, &label);
+    let mut long_id = Vec::new();
+    tag(&mut long_id, 1, TAG_ID);
+    long_id.push(0);
+    long_id.extend_from_slice(&[0x80; 10]);
+    long_id.push(0x01);
+    for content in [huge_label, long_id] {
+        let mut data = header.to_vec();
+        block(&mut data, BLOCK_TREE_NODE, 1, &content);
+        let result = crate::LinesData::parse(&mut &data[..]);
+        assert!(matches!(result, Err(Error::IOError(_))), "{:?}", result);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::DirectionVec;
+
+type Vec2 = (f32, f32);
+
+/// A path segment continuing from the end of the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PathSegment {
+    Line(Vec2),
+    /// Two control points and the end point of a cubic Bézier curve.
+    Curve(Vec2, Vec2, Vec2),
+}
+
+/// Creates the segments of a path through the points, after moving to the
+/// first one. Without a tolerance, the points are connected by straight
+/// lines. Otherwise, cubic curves that pass within `tolerance` of all
+/// points are fitted, splitting them where necessary.
+pub(crate) fn path_segments(points: &[Vec2], tolerance: Option<f32>) -> Vec<PathSegment> {
+    let mut distinct_points: Vec<Vec2> = Vec::with_capacity(points.len());
+    for &point in points {
+        if distinct_points.last() != Some(&point) {
+            distinct_points.push(point);
+        }
+    }
+    let points = distinct_points;
+
+    match tolerance {
+        Some(tolerance) if points.len() > 2 => {
+            let points: Vec<DirectionVec> = points
+                .iter()
+                .map(|&(x, y)| DirectionVec::new(x, y))
+                .collect();
+            let n = points.len();
+            let mut segments = Vec::new();
+            fit_cubic(
+                &points,
+                (points[1] - points[0]).set_length(1.0),
+                (points[n - 2] - points[n - 1]).set_length(1.0),
+                tolerance,
+                &mut segments,
+            );
+            segments
+        }
+        _ => points
+            .iter()
+            .skip(1)
+            .map(|&p| PathSegment::Line(p))
+            .collect(),
+    }
+}
+
+fn tuple(v: DirectionVec) -> Vec2 {
+    (v.x, v.y)
+}
+
+/// Fits curves to the points, starting and ending in the direction of the
+/// tangents. The tangent at the end points backwards.
+///
+/// Algorithm see Philip J. Schneider: An Algorithm for Automatically Fitting
+/// Digitized Curves, Graphics Gems, 1990.
+fn fit_cubic(
+    points: &[DirectionVec],
+    start_tangent: DirectionVec,
+    end_tangent: DirectionVec,
+    tolerance: f32,
+    segments: &mut Vec<PathSegment>,
+) {
+    if points.len() == 2 {
+        segments.push(PathSegment::Line(tuple(points[1])));
+        return;
+    }
+
+    let mut u = chord_length_parameters(points);
+    let mut bezier = least_squares_bezier(points, &u, start_tangent, end_tangent);
+    let (mut max_error, mut split) = fit_error(points, &bezier, &u);
+    // Close fits are improved by moving the parameters of the points to
+    // their nearest points on the curve before giving up and splitting.
+    if max_error < 4.0 * tolerance {
+        for _ in 0..4 {
+            if max_error <= tolerance {
+                break;
+            }
+            u = reparameterize(points, &u, &bezier);
+            bezier = least_squares_bezier(points, &u, start_tangent, end_tangent);
+            (max_error, split) = fit_error(points, &bezier, &u);
+        }
+    }
+    if max_error <= tolerance {
+        segments.push(PathSegment::Curve(
+            tuple(bezier[1]),
+            tuple(bezier[2]),
+            tuple(bezier[3]),
+        ));
+        return;
+    }
+
+    let mut center_tangent = (points[split - 1] - points[split + 1]).set_length(1.0);
+    if center_tangent == DirectionVec::ZERO {
+        center_tangent = (points[split - 1] - points[split]).set_length(1.0);
+    }
+    fit_cubic(
+        &points[..=split],
+        start_tangent,
+        center_tangent,
+        tolerance,
+        segments,
+    );
+    fit_cubic(
+        &points[split..],
+        -center_tangent,
+        end_tangent,
+        tolerance,
+        segments,
+    );
+}
+
+/// Parameters of the points in the range 0 to 1, by their distance along
+/// the polyline.
+fn chord_length_parameters(points: &[DirectionVec]) -> Vec<f32> {
+    let mut u = Vec::with_capacity(points.len());
+    let mut length = 0.0;
+    u.push(length);
+    for segment in points.windows(2) {
+        length += (segment[1] - segment[0]).length();
+        u.push(length);
+    }
+    u.iter()
+        .map(|&length_so_far| length_so_far / length)
+        .collect()
+}
+
+/// The curve from the first to the last point with the given tangents that
+/// comes closest to the points at their parameters.
+fn least_squares_bezier(
+    points: &[DirectionVec],
+    u: &[f32],
+    start_tangent: DirectionVec,
+    end_tangent: DirectionVec,
+) -> [DirectionVec; 4] {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let mut c = [[0.0; 2]; 2];
+    let mut x = [0.0; 2];
+    for (&point, &t) in points.iter().zip(u) {
+        let [b0, b1, b2, b3] = bernstein(t);
+        let a = [start_tangent * b1, end_tangent * b2];
+        c[0][0] += a[0].dot(&a[0]);
+        c[0][1] += a[0].dot(&a[1]);
+        c[1][1] += a[1].dot(&a[1]);
+        let rest = point - (first * (b0 + b1) + last * (b2 + b3));
+        x[0] += a[0].dot(&rest);
+        x[1] += a[1].dot(&rest);
+    }
+    c[1][0] = c[0][1];
+
+    let det_c0_c1 = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+    let det_c0_x = c[0][0] * x[1] - c[1][0] * x[0];
+    let det_x_c1 = x[0] * c[1][1] - x[1] * c[0][1];
+    let (mut alpha_start, mut alpha_end) = if det_c0_c1 == 0.0 {
+        (0.0, 0.0)
+    } else {
+        (det_x_c1 / det_c0_c1, det_c0_x / det_c0_c1)
+    };
+
+    // Fall back to a third of the distance for degenerate fits, and for
+    // control points that shoot far past the ends, which the error at the
+    // points does not catch.
+    let segment_length = (last - first).length();
+    let epsilon = 1.0e-6 * segment_length;
+    if alpha_start < epsilon
+        || alpha_end < epsilon
+        || alpha_start > segment_length
+        || alpha_end > segment_length
+    {
+        alpha_start = segment_length / 3.0;
+        alpha_end = alpha_start;
+    }
+
+    [
+        first,
+        first + start_tangent * alpha_start,
+        last + end_tangent * alpha_end,
+        last,
+    ]
+}
+
+/// The largest distance of a point from the curve at its parameter and the
+/// index of that point.
+fn fit_error(points: &[DirectionVec], bezier: &[DirectionVec; 4], u: &[f32]) -> (f32, usize) {
+    let mut max_error = 0.0;
+    let mut split = points.len() / 2;
+    for i in 1..points.len() - 1 {
+        let error = (evaluate(bezier, u[i]) - points[i]).length();
+        if error > max_error {
+            max_error = error;
+            split = i;
+        }
+    }
+    (max_error, split)
+}
+
+/// Improves the parameters with a Newton-Raphson step towards the nearest
+/// point on the curve.
+fn reparameterize(points: &[DirectionVec], u: &[f32], bezier: &[DirectionVec; 4]) -> Vec<f32> {
+    let derivative: [DirectionVec; 3] = [0, 1, 2].map(|i| (bezier[i + 1] - bezier[i]) * 3.0);
+    let second_derivative: [DirectionVec; 2] =
+        [0, 1].map(|i| (derivative[i + 1] - derivative[i]) * 2.0);
+    points
+        .iter()
+        .zip(u)
+        .map(|(&point, &t)| {
+            let difference = evaluate(bezier, t) - point;
+            let d1 = evaluate(&derivative, t);
+            let d2 = evaluate(&second_derivative, t);
+            let denominator = d1.dot(&d1) + difference.dot(&d2);
+            if denominator == 0.0 {
+                t
+            } else {
+                (t - difference.dot(&d1) / denominator).clamp(0.0, 1.0)
+            }
+        })
+        .collect()
+}
+
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t]
+}
+
+/// Evaluates a Bézier curve of any degree with de Casteljau's algorithm.
+fn evaluate<const N: usize>(bezier: &[DirectionVec; N], t: f32) -> DirectionVec {
+    let mut points = *bezier;
+    for degree in (1..N).rev() {
+        for i in 0..degree {
+            points[i] = points[i] * (1.0 - t) + points[i + 1] * t;
+        }
+    }
+    points[0]
+}
+
+#[test]
+fn test_path_segments() {
+    let arc: Vec<Vec2> = (0..=20)
+        .map(|i| {
+            let angle = std::f32::consts::FRAC_PI_2 * i as f32 / 20.0;
+            (100.0 * angle.cos(), 100.0 * angle.sin())
+        })
+        .collect();
+
+    assert_eq!(path_segments(&arc, None).len(), 20);
+    assert_eq!(
+        path_segments(&[(0.0, 0.0), (0.0, 0.0), (1.0, 0.0)], Some(0.5)),
+        vec![PathSegment::Line((1.0, 0.0))]
+    );
+
+    // A quarter circle is close enough to a single cubic curve.
+    let segments = path_segments(&arc, Some(0.5));
+    let bezier = match segments[..] {
+        [PathSegment::Curve(c1, c2, end)] => [arc[0], c1, c2, end],
+        _ => panic!("{:?}", segments),
+    };
+    assert_eq!(bezier[3], arc[20]);
+    let bezier = bezier.map(|(x, y)| DirectionVec::new(x, y));
+    let samples: Vec<DirectionVec> = (0..=1000)
+        .map(|i| evaluate(&bezier, i as f32 / 1000.0))
+        .collect();
+    for &point in &arc {
+        let error = samples
+            .iter()
+            .map(|&s| (s - DirectionVec::new(point.0, point.1)).length())
+            .fold(f32::INFINITY, f32::min);
+        assert!(error <= 0.5, "{:?} is {} off", point, error);
+    }
+
+    // A corner needs more than one curve.
+    let corner = [
+        (0.0, 0.0),
+        (50.0, 0.0),
+        (100.0, 0.0),
+        (100.0, 50.0),
+        (100.0, 100.0),
+    ];
+    assert!(path_segments(&corner, Some(0.5)).len() > 1);
+}
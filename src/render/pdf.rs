@@ -1,44 +1,598 @@
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 
+use crate::render::curves::{path_segments, PathSegment};
+use crate::render::renderlib::{
+    color_opacity, css_color_to_rgb, line_opacity, page_transform, render_pages, stroke_outline,
+    thinned_points, EraseStyle, RenderOptions, Renderer, StrokeStyle, HIGHLIGHTER_OPACITY,
+    WIDTH_FACTOR,
+};
+use crate::render::templates::TEMPLATES;
 use crate::*;
-use pdf_canvas::graphicsstate::{self, CapStyle, JoinStyle, Matrix};
-use pdf_canvas::Pdf;
+use pdf_writer::types::{LineCapStyle, LineJoinStyle, MaskType};
+use pdf_writer::writers::Resources;
+use pdf_writer::{Content, Name, Pdf, Rect, Ref};
+use svg2pdf::usvg::{self, TreeParsing};
 
-const BASE_LINE_WIDTH: f32 = 4.;
+/// Opacities are rounded to whole percents, so that each page only needs a
+/// limited number of graphics states.
+type Opacity = u8;
 
-pub fn render_pdf(path: &str, pages: &[Page]) -> io::Result<()> {
-    // Open our pdf document.
-    let mut document = Pdf::create(path).expect("Create PDF file");
+const TEMPLATE_NAME: &str = "Template";
 
-    // Only one page to consider.
-    let page = &pages[0];
+/// Renders pages to PDF, one PDF page per page in the given order.
+/// Strokes are drawn like `render_svg` draws them, on top of the page's
+/// template. Text in templates is not rendered.
+pub fn render_pdf(
+    output: &mut dyn io::Write,
+    pages: &[Page],
+    options: &RenderOptions,
+) -> Result<()> {
+    let mut renderer = PdfRenderer::new(output, options);
+    render_pages(&mut renderer, pages)
+}
+
+/// Renders all pages into a single PDF document, which is written when
+/// rendering is finished.
+pub struct PdfRenderer<'a> {
+    output: &'a mut dyn io::Write,
+    options: &'a RenderOptions,
+    document: Pdf,
+    next_id: Ref,
+    page_tree_id: Ref,
+    page_ids: Vec<Ref>,
+    // Graphics state objects and templates are shared by all pages.
+    graphics_states: BTreeMap<Opacity, Ref>,
+    template_ids: HashMap<&'static str, Ref>,
+    /// The content stream of the current page, without `page_content`.
+    page_stream: Vec<u8>,
+    page_resources: ContentResources,
+    /// Content of the current layer, or of the page before the first layer.
+    /// It is kept apart from `page_stream`, so that erasers can mask it.
+    page_content: PageContent,
+    /// Soft mask of the erasers since the last line, applied to
+    /// `page_content` before the next line is drawn.
+    eraser_mask: Option<Content>,
+    /// Size of the current page, swapped for landscape pages.
+    media_box: Rect,
+}
+
+impl<'a> PdfRenderer<'a> {
+    pub fn new(output: &'a mut dyn io::Write, options: &'a RenderOptions) -> Self {
+        let mut document = Pdf::new();
+        let mut next_id = Ref::new(1);
+        let catalog_id = next_id.bump();
+        let page_tree_id = next_id.bump();
+        document.catalog(catalog_id).pages(page_tree_id);
+
+        PdfRenderer {
+            output,
+            options,
+            document,
+            next_id,
+            page_tree_id,
+            page_ids: Vec::new(),
+            graphics_states: BTreeMap::new(),
+            template_ids: HashMap::new(),
+            page_stream: Vec::new(),
+            page_resources: ContentResources::default(),
+            page_content: PageContent::new(),
+            eraser_mask: None,
+            media_box: Rect::new(0.0, 0.0, options.page_width, options.page_height),
+        }
+    }
+
+    /// Returns the id of the template's form XObject, adding it to the
+    /// document when it is used for the first time.
+    fn template_id(&mut self, template: &str) -> Result<Option<Ref>> {
+        let (&name, &svg) = match TEMPLATES.get_entry(template) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Some(&id) = self.template_ids.get(name) {
+            return Ok(Some(id));
+        }
+        let id = self.next_id;
+        let tree = usvg::Tree::from_str(svg, &usvg::Options::default())
+            .map_err(|e| Error::InvalidTemplate(name.to_string(), e.to_string()))?;
+        self.next_id =
+            svg2pdf::convert_tree_into(&tree, svg2pdf::Options::default(), &mut self.document, id);
+        self.template_ids.insert(name, id);
+        Ok(Some(id))
+    }
+
+    /// Adds the graphics states for the opacities that are not in the
+    /// document yet.
+    fn add_graphics_states(&mut self, opacities: &[Opacity]) {
+        for opacity in opacities {
+            self.graphics_states.entry(*opacity).or_insert_with(|| {
+                let id = self.next_id.bump();
+                let alpha = f32::from(*opacity) / 100.0;
+                self.document
+                    .ext_graphics(id)
+                    .stroking_alpha(alpha)
+                    .non_stroking_alpha(alpha);
+                id
+            });
+        }
+    }
+
+    /// Appends `page_content` to the content stream of the page.
+    fn flush_page_content(&mut self) {
+        let PageContent {
+            content, resources, ..
+        } = std::mem::replace(&mut self.page_content, PageContent::new());
+        let data = content.finish();
+        if data.is_empty() {
+            return;
+        }
+        if !self.page_stream.is_empty() {
+            self.page_stream.push(b'\n');
+        }
+        self.page_stream.extend(data);
+        self.page_resources.extend(resources);
+    }
+
+    /// Moves the ink drawn so far in the layer into a form XObject, which is
+    /// drawn with the soft mask of the pending erasers. Consecutive erasers
+    /// share a mask, and each masked form holds the previous one with the
+    /// ink drawn since.
+    fn apply_eraser_mask(&mut self) {
+        let mask = match self.eraser_mask.take() {
+            Some(mask) => mask,
+            None => return,
+        };
+        let bbox = self.page_rect();
+
+        let PageContent {
+            content, resources, ..
+        } = std::mem::replace(&mut self.page_content, PageContent::new());
+        self.add_graphics_states(&resources.opacities);
+        let ink_id = self.next_id.bump();
+        let ink = content.finish();
+        let mut ink_form = self.document.form_xobject(ink_id, &ink);
+        ink_form.bbox(bbox);
+        write_resources(&mut ink_form.resources(), &resources, &self.graphics_states);
+        drop(ink_form);
+
+        let mask_id = self.next_id.bump();
+        let mask = mask.finish();
+        let mut mask_form = self.document.form_xobject(mask_id, &mask);
+        mask_form.bbox(bbox);
+        mask_form.group().transparency().color_space().device_gray();
+        drop(mask_form);
+
+        let mask_state_id = self.next_id.bump();
+        self.document
+            .ext_graphics(mask_state_id)
+            .soft_mask()
+            .subtype(MaskType::Luminosity)
+            .group(mask_id);
+
+        let ink_name = format!("Ink{}", ink_id.get());
+        let mask_state_name = format!("Eraser{}", mask_state_id.get());
+        self.page_content
+            .content
+            .save_state()
+            .set_parameters(Name(mask_state_name.as_bytes()))
+            .x_object(Name(ink_name.as_bytes()))
+            .restore_state();
+        let resources = &mut self.page_content.resources;
+        resources.x_objects.push((ink_name, ink_id));
+        resources
+            .ext_g_states
+            .push((mask_state_name, mask_state_id));
+    }
+
+    /// The page in the coordinates of the .rm files.
+    fn page_rect(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.options.page_width, self.options.page_height)
+    }
+}
+
+impl Renderer for PdfRenderer<'_> {
+    fn options(&self) -> &RenderOptions {
+        self.options
+    }
+
+    fn begin_page(&mut self, page: &Page, template: Option<&str>) -> Result<()> {
+        self.page_stream = Vec::new();
+        self.page_resources = ContentResources::default();
+        self.page_content = PageContent::new();
+        self.eraser_mask = None;
+        let (width, height) = (self.options.page_width, self.options.page_height);
+
+        let (page_width, page_height, matrix) = page_transform(page, self.options);
+        self.media_box = Rect::new(0.0, 0.0, page_width, page_height);
+        if matrix != Matrix::identity() {
+            // The page is drawn in portrait PDF coordinates, so the
+            // transformation is wrapped into flips of the Y axis.
+            let pdf_matrix =
+                Matrix::mirror_y(0.5 * page_height) * matrix * Matrix::mirror_y(0.5 * height);
+            self.page_content.content.transform(pdf_matrix.0);
+        }
+
+        let template_id = template.map(|t| self.template_id(t)).transpose()?;
+        if let Some(template_id) = template_id.flatten() {
+            // The template XObject fills the unit square.
+            self.page_content
+                .content
+                .save_state()
+                .transform([width, 0.0, 0.0, height, 0.0, 0.0])
+                .x_object(Name(TEMPLATE_NAME.as_bytes()))
+                .restore_state();
+            self.page_content
+                .resources
+                .x_objects
+                .push((TEMPLATE_NAME.to_string(), template_id));
+        }
 
-    document.render_page(1404.0, 1872.0, |c| {
         // Inverse Y coordinate system.
-        c.concat(Matrix::scale(1., -1.))?;
-        c.concat(Matrix::translate(0., -1872.))?;
-
-        c.set_stroke_color(graphicsstate::Color::gray(0))?;
-
-        for layer in &page.layers {
-            for line in &layer.lines {
-                if line.points.is_empty() {
-                    continue;
-                }
-                let first_point = &line.points[0];
-                c.move_to(first_point.x, first_point.y)?;
-                for point in &line.points {
-                    c.set_line_width(point.pressure * BASE_LINE_WIDTH)?;
-                    c.set_line_cap_style(CapStyle::Round)?;
-                    c.set_line_join_style(JoinStyle::Round)?;
-                    c.line_to(point.x, point.y)?;
-                }
-                c.stroke()?;
-            }
+        self.page_content
+            .content
+            .transform([1.0, 0.0, 0.0, -1.0, 0.0, height]);
+        Ok(())
+    }
+
+    fn begin_layer(&mut self, _layer: &Layer) -> Result<()> {
+        self.flush_page_content();
+        Ok(())
+    }
+
+    fn draw_line(&mut self, line: &Line, style: StrokeStyle, css_color: &str) -> Result<()> {
+        self.apply_eraser_mask();
+        let [r, g, b] = css_color_to_rgb(css_color);
+        self.page_content
+            .content
+            .set_stroke_rgb(r, g, b)
+            .set_fill_rgb(r, g, b);
+        match style {
+            StrokeStyle::ConstantWidth => render_constant_width_line(
+                &mut self.page_content,
+                line,
+                self.options.distance_threshold,
+                self.options.curve_tolerance,
+            ),
+            StrokeStyle::VariableWidth => render_variable_width_line(
+                &mut self.page_content,
+                line,
+                self.options.distance_threshold,
+                self.options.curve_tolerance,
+            ),
         }
+        Ok(())
+    }
+
+    /// Adds the eraser to the mask of the ink drawn so far, see
+    /// `PdfRenderer::apply_eraser_mask`.
+    fn erase(&mut self, line: &Line, style: EraseStyle) -> Result<()> {
+        let (first_point, last_point) = match (line.points.first(), line.points.last()) {
+            (Some(first_point), Some(last_point)) => (first_point, last_point),
+            _ => return Ok(()),
+        };
+        let bbox = self.page_rect();
+        // The mask shows the ink where it is white and hides it where it is
+        // black.
+        let mask = self.eraser_mask.get_or_insert_with(|| {
+            let mut mask = Content::new();
+            mask.set_fill_gray(1.0)
+                .rect(bbox.x1, bbox.y1, bbox.x2 - bbox.x1, bbox.y2 - bbox.y1)
+                .fill_nonzero();
+            mask
+        });
+        match style {
+            EraseStyle::Stroke => mask
+                .set_stroke_gray(0.0)
+                .set_line_width(last_point.width * WIDTH_FACTOR)
+                .set_line_cap(LineCapStyle::RoundCap)
+                .set_line_join(LineJoinStyle::RoundJoin),
+            EraseStyle::Area => mask.set_fill_gray(0.0),
+        };
+        mask.move_to(first_point.x, first_point.y);
+        for point in &line.points[1..] {
+            mask.line_to(point.x, point.y);
+        }
+        match style {
+            // A degenerate subpath is stroked as a dot with round caps.
+            EraseStyle::Stroke if line.points.len() == 1 => {
+                mask.line_to(first_point.x, first_point.y).stroke()
+            }
+            EraseStyle::Stroke => mask.stroke(),
+            EraseStyle::Area => mask.close_path().fill_nonzero(),
+        };
+        Ok(())
+    }
+
+    fn end_layer(&mut self) -> Result<()> {
+        self.apply_eraser_mask();
+        self.flush_page_content();
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<()> {
+        self.flush_page_content();
+        let page_id = self.next_id.bump();
+        let content_id = self.next_id.bump();
+        self.page_ids.push(page_id);
+
+        let page_stream = std::mem::take(&mut self.page_stream);
+        self.document.stream(content_id, &page_stream);
+
+        let resources = std::mem::take(&mut self.page_resources);
+        self.add_graphics_states(&resources.opacities);
+
+        let mut pdf_page = self.document.page(page_id);
+        pdf_page
+            .parent(self.page_tree_id)
+            .media_box(self.media_box)
+            .contents(content_id);
+        write_resources(&mut pdf_page.resources(), &resources, &self.graphics_states);
+        Ok(())
+    }
 
+    fn finish(&mut self) -> Result<()> {
+        let page_count = self.page_ids.len() as i32;
+        self.document
+            .pages(self.page_tree_id)
+            .kids(self.page_ids.iter().copied())
+            .count(page_count);
+
+        let document = std::mem::replace(&mut self.document, Pdf::new());
+        self.output.write_all(&document.finish())?;
         Ok(())
-    })?;
-    document.finish()?;
-    Ok(())
+    }
+}
+
+fn graphics_state_name(opacity: Opacity) -> String {
+    format!("GS{}", opacity)
+}
+
+/// The named resources a content stream uses.
+#[derive(Default)]
+struct ContentResources {
+    opacities: Vec<Opacity>,
+    x_objects: Vec<(String, Ref)>,
+    ext_g_states: Vec<(String, Ref)>,
+}
+
+impl ContentResources {
+    fn extend(&mut self, other: ContentResources) {
+        for opacity in other.opacities {
+            if !self.opacities.contains(&opacity) {
+                self.opacities.push(opacity);
+            }
+        }
+        self.x_objects.extend(other.x_objects);
+        self.ext_g_states.extend(other.ext_g_states);
+    }
+}
+
+/// Writes the resource dictionary of a page or form. The graphics states of
+/// all opacities must have been added already.
+fn write_resources(
+    resources: &mut Resources,
+    content_resources: &ContentResources,
+    graphics_states: &BTreeMap<Opacity, Ref>,
+) {
+    if !content_resources.x_objects.is_empty() {
+        let mut x_objects = resources.x_objects();
+        for (name, id) in &content_resources.x_objects {
+            x_objects.pair(Name(name.as_bytes()), *id);
+        }
+    }
+    let mut ext_g_states = resources.ext_g_states();
+    for opacity in &content_resources.opacities {
+        ext_g_states.pair(
+            Name(graphics_state_name(*opacity).as_bytes()),
+            graphics_states[opacity],
+        );
+    }
+    for (name, id) in &content_resources.ext_g_states {
+        ext_g_states.pair(Name(name.as_bytes()), *id);
+    }
+}
+
+/// The content stream of a page or form together with the resources it
+/// uses.
+struct PageContent {
+    content: Content,
+    resources: ContentResources,
+    opacity: Option<Opacity>,
+}
+
+impl PageContent {
+    fn new() -> Self {
+        PageContent {
+            content: Content::new(),
+            resources: ContentResources::default(),
+            opacity: None,
+        }
+    }
+
+    fn set_opacity(&mut self, opacity: f32) -> &mut Content {
+        let opacity = (opacity.clamp(0.0, 1.0) * 100.0).round() as Opacity;
+        if self.opacity != Some(opacity) {
+            if !self.resources.opacities.contains(&opacity) {
+                self.resources.opacities.push(opacity);
+            }
+            self.content
+                .set_parameters(Name(graphics_state_name(opacity).as_bytes()));
+            self.opacity = Some(opacity);
+        }
+        &mut self.content
+    }
+}
+
+fn render_constant_width_line(
+    page_content: &mut PageContent,
+    line: &Line,
+    distance_threshold: f32,
+    curve_tolerance: Option<f32>,
+) {
+    let last_point = &line.points[line.points.len() - 1];
+    let content = match line.brush_type {
+        BrushType::Highlighter => page_content
+            .set_opacity(HIGHLIGHTER_OPACITY * color_opacity(line))
+            .set_line_width(last_point.width)
+            .set_line_cap(LineCapStyle::ButtCap),
+        _ => page_content
+            .set_opacity(color_opacity(line))
+            .set_line_width(last_point.width * WIDTH_FACTOR)
+            .set_line_cap(LineCapStyle::RoundCap),
+    };
+    content.set_line_join(LineJoinStyle::RoundJoin);
+
+    let points = thinned_points(line, distance_threshold);
+    path(content, &points, curve_tolerance);
+    content.stroke();
+}
+
+/// Fills the outline of the line, so that the width can follow the points.
+fn render_variable_width_line(
+    page_content: &mut PageContent,
+    line: &Line,
+    distance_threshold: f32,
+    curve_tolerance: Option<f32>,
+) {
+    let outline = stroke_outline(line, distance_threshold);
+    if outline.is_empty() {
+        return;
+    }
+    let content = page_content.set_opacity(line_opacity(line));
+    path(content, &outline, curve_tolerance);
+    content.close_path().fill_nonzero();
+}
+
+/// Adds a path through the points, optionally fitting curves to them.
+fn path(content: &mut Content, points: &[(f32, f32)], curve_tolerance: Option<f32>) {
+    if let Some(first_point) = points.first() {
+        content.move_to(first_point.0, first_point.1);
+    }
+    for segment in path_segments(points, curve_tolerance) {
+        match segment {
+            PathSegment::Line(point) => content.line_to(point.0, point.1),
+            PathSegment::Curve(c1, c2, point) => {
+                content.cubic_to(c1.0, c1.1, c2.0, c2.1, point.0, point.1)
+            }
+        };
+    }
+}
+
+#[test]
+fn test_render_pdf_pages() {
+    let landscape_page = Page {
+        orientation: Orientation::Landscape,
+        ..Default::default()
+    };
+    let pages = [Page::default(), landscape_page, Page::default()];
+    let mut pdf = Vec::new();
+    render_pdf(&mut pdf, &pages, &RenderOptions::new()).unwrap();
+
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert_eq!(pdf.matches("/Type /Page\n").count(), 3);
+    assert_eq!(pdf.matches("/MediaBox [0 0 1404 1872]").count(), 2);
+    assert_eq!(pdf.matches("/MediaBox [0 0 1872 1404]").count(), 1);
+}
+
+#[test]
+fn test_render_pdf_templates() {
+    let pages = [
+        Page {
+            template: Some("P Grid medium".to_string()),
+            ..Default::default()
+        },
+        Page::default(),
+        Page {
+            template: Some("P Grid medium".to_string()),
+            ..Default::default()
+        },
+    ];
+    let mut pdf = Vec::new();
+    render_pdf(&mut pdf, &pages, &RenderOptions::new()).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert_eq!(pdf.matches("/Subtype /Form").count(), 1);
+    assert_eq!(pdf.matches("/Template Do").count(), 2);
+
+    let mut pdf = Vec::new();
+    let options = RenderOptions::new().template("P Lines small");
+    render_pdf(&mut pdf, &pages, &options).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert_eq!(pdf.matches("/Template Do").count(), 3);
+
+    let options = RenderOptions::new().template("No template");
+    assert!(render_pdf(&mut Vec::new(), &pages, &options).is_err());
+}
+
+#[test]
+fn test_render_pdf_eraser() {
+    let line = |brush_type| Line::with_brush(brush_type, 10.0, &[(100.0, 100.0), (300.0, 100.0)]);
+    let page = |lines| Page {
+        layers: vec![Layer {
+            lines,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let render = |page| {
+        let mut pdf = Vec::new();
+        render_pdf(&mut pdf, &[page], &RenderOptions::new()).unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    };
+
+    let pdf = render(page(vec![line(BrushType::Fineliner)]));
+    assert!(!pdf.contains("/SMask"));
+
+    let pdf = render(page(vec![
+        line(BrushType::Fineliner),
+        line(BrushType::Eraser),
+        line(BrushType::Fineliner),
+    ]));
+    assert_eq!(pdf.matches("/S /Luminosity").count(), 1);
+    assert_eq!(pdf.matches("/Subtype /Form").count(), 2);
+    assert!(pdf.contains("/Eraser"));
+    // Erasers are as wide as ink strokes.
+    assert!(pdf.contains("8 w"));
+
+    // Consecutive erasers share a mask, a single point eraser is a dot.
+    let pdf = render(page(vec![
+        line(BrushType::Fineliner),
+        line(BrushType::Eraser),
+        Line::with_brush(BrushType::Eraser, 10.0, &[(200.0, 200.0)]),
+        line(BrushType::Fineliner),
+        line(BrushType::Eraser),
+    ]));
+    assert_eq!(pdf.matches("/S /Luminosity").count(), 2);
+    assert!(pdf.contains("200 200 m\n200 200 l\nS"));
+}
+
+#[test]
+fn test_render_pdf_distance_threshold() {
+    let line = Line::with_brush(
+        BrushType::Fineliner,
+        2.0,
+        &[
+            (100.0, 100.0),
+            (100.5, 100.0),
+            (101.0, 100.0),
+            (200.0, 100.0),
+        ],
+    );
+    let page = Page {
+        layers: vec![Layer {
+            lines: vec![line],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let render = |options: &RenderOptions| {
+        let mut pdf = Vec::new();
+        render_pdf(&mut pdf, std::slice::from_ref(&page), options).unwrap();
+        String::from_utf8_lossy(&pdf).into_owned()
+    };
+
+    // Points closer than the threshold to the previous point are left out.
+    let pdf = render(&RenderOptions::new());
+    assert!(pdf.contains("100 100 m\n200 100 l\nS"));
+    let pdf = render(&RenderOptions::new().distance_threshold(0.0));
+    assert!(pdf.contains("100 100 m\n100.5 100 l\n101 100 l\n200 100 l\nS"));
 }
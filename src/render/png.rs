@@ -0,0 +1,425 @@
+use crate::render::renderlib::{
+    color_opacity, css_color_to_rgb, line_opacity, page_transform, render_pages, stroke_outline,
+    BoundingBox, EraseStyle, RenderOptions, Renderer, StrokeStyle, HIGHLIGHTER_OPACITY, SCREEN_DPI,
+    WIDTH_FACTOR,
+};
+use crate::render::templates::TEMPLATES;
+use crate::{BrushType, Error, Layer, Line, Page, Result};
+use resvg::tiny_skia::{
+    self, BlendMode, Color, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, PixmapPaint,
+    Stroke, Transform,
+};
+use resvg::usvg::{self, TreeParsing};
+use std::io;
+
+/// Renders a page to PNG at the resolution of the options. Strokes are
+/// drawn like `render_svg` draws them. The image is transparent unless a
+/// background color is given. Text in templates is not rendered.
+pub fn render_png(output: &mut dyn io::Write, page: &Page, options: &RenderOptions) -> Result<()> {
+    let mut renderer = PngRenderer::new(output, options);
+    render_pages(&mut renderer, std::slice::from_ref(page))
+}
+
+/// Renders a page to a PNG image. Beginning a second page fails with
+/// `Error::MultiplePages`.
+pub struct PngRenderer<'a> {
+    output: &'a mut dyn io::Write,
+    options: &'a RenderOptions,
+    pixmap: Option<Pixmap>,
+    /// Layers are drawn separately, so that erasers only affect their layer.
+    layer_pixmap: Option<Pixmap>,
+    transform: Transform,
+    page_begun: bool,
+}
+
+impl<'a> PngRenderer<'a> {
+    pub fn new(output: &'a mut dyn io::Write, options: &'a RenderOptions) -> Self {
+        PngRenderer {
+            output,
+            options,
+            pixmap: None,
+            layer_pixmap: None,
+            transform: Transform::identity(),
+            page_begun: false,
+        }
+    }
+}
+
+impl Renderer for PngRenderer<'_> {
+    fn options(&self) -> &RenderOptions {
+        self.options
+    }
+
+    fn begin_page(&mut self, page: &Page, template: Option<&str>) -> Result<()> {
+        if self.page_begun {
+            return Err(Error::MultiplePages("PNG"));
+        }
+        self.page_begun = true;
+        let (page_width, page_height, matrix) = page_transform(page, self.options);
+        let bounding_box = BoundingBox::new().enclose_page(page).transformed(matrix);
+        let (min_x, min_y, width, height) =
+            if self.options.auto_crop && bounding_box.min_x.is_finite() {
+                (
+                    bounding_box.min_x,
+                    bounding_box.min_y,
+                    bounding_box.max_x - bounding_box.min_x,
+                    bounding_box.max_y - bounding_box.min_y,
+                )
+            } else {
+                (0.0, 0.0, page_width, page_height)
+            };
+        let scale = self.options.dpi / SCREEN_DPI;
+        let pixel_size = |size: f32| (size * scale).ceil().max(1.0) as u32;
+        let mut pixmap = Pixmap::new(pixel_size(width), pixel_size(height))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid image size"))?;
+        let [a, b, c, d, e, f] = matrix.0;
+        let transform = Transform::from_row(scale, 0.0, 0.0, scale, -min_x * scale, -min_y * scale)
+            .pre_concat(Transform::from_row(a, b, c, d, e, f));
+
+        if let Some(background) = &self.options.background {
+            let [r, g, b] = css_color_to_rgb(background);
+            pixmap.fill(Color::from_rgba(r, g, b, 1.0).unwrap_or(Color::WHITE));
+        }
+
+        if let Some((&name, &template)) =
+            template.and_then(|template| TEMPLATES.get_entry(template))
+        {
+            let mut tree = usvg::Tree::from_str(template, &usvg::Options::default())
+                .map_err(|e| Error::InvalidTemplate(name.to_string(), e.to_string()))?;
+            // resvg skips nodes without bounding boxes.
+            tree.calculate_abs_transforms();
+            tree.calculate_bounding_boxes();
+            // Template sizes are given in pt, we stretch them to the page.
+            let template_transform = transform.pre_scale(
+                self.options.page_width / tree.size.width(),
+                self.options.page_height / tree.size.height(),
+            );
+            resvg::render(&tree, template_transform, &mut pixmap.as_mut());
+        }
+
+        self.pixmap = Some(pixmap);
+        self.transform = transform;
+        Ok(())
+    }
+
+    fn begin_layer(&mut self, _layer: &Layer) -> Result<()> {
+        self.layer_pixmap = self
+            .pixmap
+            .as_ref()
+            .and_then(|pixmap| Pixmap::new(pixmap.width(), pixmap.height()));
+        Ok(())
+    }
+
+    fn draw_line(&mut self, line: &Line, style: StrokeStyle, css_color: &str) -> Result<()> {
+        if let Some(pixmap) = self.layer_pixmap.as_mut().or(self.pixmap.as_mut()) {
+            let rgb = css_color_to_rgb(css_color);
+            match style {
+                StrokeStyle::ConstantWidth => {
+                    render_constant_width_line(pixmap, self.transform, line, rgb)
+                }
+                StrokeStyle::VariableWidth => render_variable_width_line(
+                    pixmap,
+                    self.transform,
+                    line,
+                    rgb,
+                    self.options.distance_threshold,
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, line: &Line, style: EraseStyle) -> Result<()> {
+        let pixmap = match self.layer_pixmap.as_mut() {
+            Some(pixmap) => pixmap,
+            None => return Ok(()),
+        };
+        let mut paint = Paint {
+            blend_mode: BlendMode::Clear,
+            anti_alias: true,
+            ..Default::default()
+        };
+        paint.set_color(Color::BLACK);
+        match (style, &line.points[..]) {
+            (EraseStyle::Stroke, [point]) => {
+                let radius = 0.5 * point.width * WIDTH_FACTOR;
+                if let Some(path) = PathBuilder::from_circle(point.x, point.y, radius) {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, self.transform, None);
+                }
+            }
+            (EraseStyle::Stroke, _) => {
+                if let (Some(path), Some(last_point)) = (line_path(line, false), line.points.last())
+                {
+                    let stroke = Stroke {
+                        width: last_point.width * WIDTH_FACTOR,
+                        line_cap: LineCap::Round,
+                        line_join: LineJoin::Round,
+                        ..Default::default()
+                    };
+                    pixmap.stroke_path(&path, &paint, &stroke, self.transform, None);
+                }
+            }
+            (EraseStyle::Area, _) => {
+                if let Some(path) = line_path(line, true) {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, self.transform, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn end_layer(&mut self) -> Result<()> {
+        if let (Some(pixmap), Some(layer_pixmap)) = (self.pixmap.as_mut(), self.layer_pixmap.take())
+        {
+            pixmap.draw_pixmap(
+                0,
+                0,
+                layer_pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+        }
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<()> {
+        match self.pixmap.take() {
+            Some(pixmap) => write_png(self.output, &pixmap, self.options.dpi),
+            None => Ok(()),
+        }
+    }
+}
+
+fn paint(rgb: [f32; 3], opacity: f32) -> Paint<'static> {
+    let [r, g, b] = rgb;
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgba(r, g, b, opacity.clamp(0.0, 1.0)).unwrap_or(Color::BLACK));
+    paint.anti_alias = true;
+    paint
+}
+
+/// A path through the points of the line, `None` for less than two points.
+fn line_path(line: &Line, close: bool) -> Option<tiny_skia::Path> {
+    let (first_point, points) = line.points.split_first()?;
+    let mut path = PathBuilder::new();
+    path.move_to(first_point.x, first_point.y);
+    for point in points {
+        path.line_to(point.x, point.y);
+    }
+    if close {
+        path.close();
+    }
+    path.finish()
+}
+
+fn render_constant_width_line(
+    pixmap: &mut Pixmap,
+    transform: Transform,
+    line: &Line,
+    rgb: [f32; 3],
+) {
+    let (path, last_point) = match (line_path(line, false), line.points.last()) {
+        (Some(path), Some(last_point)) => (path, last_point),
+        _ => return,
+    };
+
+    let (width, line_cap, opacity) = match line.brush_type {
+        BrushType::Highlighter => (last_point.width, LineCap::Butt, HIGHLIGHTER_OPACITY),
+        _ => (last_point.width * WIDTH_FACTOR, LineCap::Round, 1.0),
+    };
+    let opacity = opacity * color_opacity(line);
+    let stroke = Stroke {
+        width,
+        line_cap,
+        line_join: LineJoin::Round,
+        ..Default::default()
+    };
+    pixmap.stroke_path(&path, &paint(rgb, opacity), &stroke, transform, None);
+}
+
+/// Fills the outline of the line, so that the width can follow the points.
+fn render_variable_width_line(
+    pixmap: &mut Pixmap,
+    transform: Transform,
+    line: &Line,
+    rgb: [f32; 3],
+    distance_threshold: f32,
+) {
+    let outline = stroke_outline(line, distance_threshold);
+    let (first_point, outline) = match outline.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut path = PathBuilder::new();
+    path.move_to(first_point.0, first_point.1);
+    for point in outline {
+        path.line_to(point.0, point.1);
+    }
+    path.close();
+    if let Some(path) = path.finish() {
+        let paint = paint(rgb, line_opacity(line));
+        pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+    }
+}
+
+/// Writes the pixmap as 8 bit RGBA PNG, recording the resolution.
+fn write_png(output: &mut dyn io::Write, pixmap: &Pixmap, dpi: f32) -> Result<()> {
+    let mut encoder = png::Encoder::new(output, pixmap.width(), pixmap.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels_per_meter = (dpi / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+
+    // Pixmaps store premultiplied colors.
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let mut writer = encoder.write_header().map_err(io::Error::from)?;
+    writer.write_image_data(&data).map_err(io::Error::from)?;
+    writer.finish().map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Decodes PNG output to width, height and RGBA data.
+#[cfg(test)]
+fn decode_png(output: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut reader = png::Decoder::new(output).read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    (info.width, info.height, data)
+}
+
+#[test]
+fn test_render_png() {
+    use crate::{Layer, Point};
+
+    let mut page = Page {
+        layers: vec![Layer {
+            lines: vec![Line::with_points(
+                Point {
+                    width: 10.0,
+                    pressure: 1.0,
+                    ..Default::default()
+                },
+                &[(100.0, 100.0), (200.0, 100.0)][..],
+            )],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let render = |page: &Page, auto_crop, dpi| {
+        let mut output = Vec::new();
+        let options = RenderOptions::new()
+            .auto_crop(auto_crop)
+            .dpi(dpi)
+            .background("white");
+        render_png(&mut output, page, &options).unwrap();
+        decode_png(&output)
+    };
+
+    let (width, height, data) = render(&page, false, SCREEN_DPI / 2.0);
+    assert_eq!((width, height), (702, 936));
+    let pixel = |x: u32, y: u32| {
+        let offset = 4 * (y * width + x) as usize;
+        &data[offset..offset + 4]
+    };
+    assert_eq!(pixel(0, 0), [255, 255, 255, 255]);
+    assert_eq!(pixel(75, 50), [0, 0, 0, 255]);
+
+    let (width, height, _) = render(&page, true, SCREEN_DPI);
+    assert_eq!((width, height), (110, 10));
+
+    // Landscape pages are turned a quarter clockwise.
+    page.orientation = crate::Orientation::Landscape;
+    let (width, height, data) = render(&page, false, SCREEN_DPI / 2.0);
+    assert_eq!((width, height), (936, 702));
+    let offset = 4 * (75 * width + 886) as usize;
+    assert_eq!(&data[offset..offset + 4], [0, 0, 0, 255]);
+    let (width, height, _) = render(&page, true, SCREEN_DPI);
+    assert_eq!((width, height), (10, 110));
+}
+
+#[test]
+fn test_render_png_template() {
+    let page = Page {
+        template: Some("P Grid medium".to_string()),
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    render_png(&mut output, &page, &RenderOptions::new()).unwrap();
+    let (_, _, data) = decode_png(&output);
+
+    // The template covers the page with white and draws grid lines on top.
+    assert!(data.chunks(4).all(|pixel| pixel[3] == 255));
+    assert!(data.chunks(4).any(|pixel| pixel[0] < 128));
+
+    // A PNG image holds a single page.
+    let options = RenderOptions::new();
+    let mut output = Vec::new();
+    let mut renderer = PngRenderer::new(&mut output, &options);
+    let pages = [Page::default(), Page::default()];
+    assert!(matches!(
+        render_pages(&mut renderer, &pages),
+        Err(Error::MultiplePages("PNG"))
+    ));
+}
+
+#[test]
+fn test_render_png_eraser() {
+    use crate::Layer;
+
+    let page = Page {
+        layers: vec![
+            Layer {
+                lines: vec![Line::with_brush(
+                    BrushType::Fineliner,
+                    10.0,
+                    &[(100.0, 100.0), (300.0, 100.0)],
+                )],
+                ..Default::default()
+            },
+            Layer {
+                lines: vec![
+                    Line::with_brush(
+                        BrushType::Fineliner,
+                        10.0,
+                        &[(100.0, 120.0), (300.0, 120.0)],
+                    ),
+                    Line::with_brush(BrushType::Eraser, 20.0, &[(150.0, 50.0), (150.0, 150.0)]),
+                    Line::with_brush(BrushType::Eraser, 20.0, &[(250.0, 120.0)]),
+                    Line::with_brush(
+                        BrushType::Fineliner,
+                        10.0,
+                        &[(100.0, 140.0), (300.0, 140.0)],
+                    ),
+                ],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    render_png(&mut output, &page, &RenderOptions::new()).unwrap();
+    let (width, _, data) = decode_png(&output);
+    let alpha = |x: u32, y: u32| data[4 * (y * width + x) as usize + 3];
+
+    // Only ink drawn before the eraser in its layer is erased.
+    assert_eq!(alpha(150, 100), 255);
+    assert_eq!(alpha(150, 120), 0);
+    assert_eq!(alpha(200, 120), 255);
+    assert_eq!(alpha(150, 140), 255);
+    // Erasers are as wide as ink strokes, a single point erases a dot.
+    assert_eq!(alpha(159, 120), 255);
+    assert_eq!(alpha(250, 120), 0);
+    assert_eq!(alpha(260, 120), 255);
+}
@@ -1,23 +1,345 @@
-use crate::{BrushType, Color, LayerColor, Line, Page, Point};
-use core::f32::{INFINITY, NEG_INFINITY};
+use crate::render::templates::TEMPLATES;
+use crate::{
+    BrushType, Color, Error, Layer, LayerColor, Line, Matrix, Orientation, Page, Point, Result,
+};
+use std::f32::consts::PI;
 
-pub(crate) struct BoundingBox {
+/// Factor from the point widths stored in the file to stroke widths.
+/// Highlighter strokes are drawn at their full width.
+pub(crate) const WIDTH_FACTOR: f32 = 0.8;
+
+pub(crate) const HIGHLIGHTER_OPACITY: f32 = 0.25;
+
+/// Size of a page in the coordinates of the .rm files.
+pub const PAGE_WIDTH: f32 = 1404.0;
+pub const PAGE_HEIGHT: f32 = 1872.0;
+
+/// Resolution of the tablet's screen. Rendering at this resolution maps each
+/// unit of the .rm coordinates to one pixel.
+pub const SCREEN_DPI: f32 = 226.0;
+
+/// Options shared by all renderers. Start from `RenderOptions::new()` and
+/// set options with the builder methods, options a renderer does not
+/// support are ignored.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RenderOptions {
+    /// Page size in the coordinates of the .rm files.
+    pub page_width: f32,
+    pub page_height: f32,
+    /// Crop pages to their content instead of the page size (SVG and PNG).
+    pub auto_crop: bool,
+    /// Colors per layer. Layers without colors use the colors of the last
+    /// layer that has some, or the defaults.
+    pub layer_colors: Vec<LayerColor>,
+    /// Template drawn on all pages instead of the pages' own templates.
+    pub template: Option<String>,
+    /// Points closer than this to the previous point are left out.
+    pub distance_threshold: f32,
+    /// Simplify lines before drawing them, leaving out points that deviate
+    /// at most this far from the simplified line. See `Line::simplified`.
+    pub simplify_tolerance: Option<f32>,
+    /// Draw lines as cubic curves that pass at most this far from the
+    /// points instead of straight segments (SVG and PDF).
+    pub curve_tolerance: Option<f32>,
+    /// Also render layers that are hidden on the tablet.
+    pub hidden_layers: bool,
+    /// Add tooltips with the line and point data (SVG).
+    pub debug_dump: bool,
+    /// Resolution of raster images (PNG).
+    pub dpi: f32,
+    /// CSS color to fill raster images with, transparent if `None` (PNG).
+    pub background: Option<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            page_width: PAGE_WIDTH,
+            page_height: PAGE_HEIGHT,
+            auto_crop: false,
+            layer_colors: Vec::new(),
+            template: None,
+            distance_threshold: 2.0,
+            simplify_tolerance: None,
+            curve_tolerance: None,
+            hidden_layers: false,
+            debug_dump: false,
+            dpi: SCREEN_DPI,
+            background: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn page_size(mut self, width: f32, height: f32) -> Self {
+        self.page_width = width;
+        self.page_height = height;
+        self
+    }
+
+    pub fn auto_crop(mut self, auto_crop: bool) -> Self {
+        self.auto_crop = auto_crop;
+        self
+    }
+
+    pub fn layer_colors(mut self, layer_colors: Vec<LayerColor>) -> Self {
+        self.layer_colors = layer_colors;
+        self
+    }
+
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    pub fn distance_threshold(mut self, distance_threshold: f32) -> Self {
+        self.distance_threshold = distance_threshold;
+        self
+    }
+
+    pub fn simplify_tolerance(mut self, simplify_tolerance: f32) -> Self {
+        self.simplify_tolerance = Some(simplify_tolerance);
+        self
+    }
+
+    pub fn curve_tolerance(mut self, curve_tolerance: f32) -> Self {
+        self.curve_tolerance = Some(curve_tolerance);
+        self
+    }
+
+    pub fn hidden_layers(mut self, hidden_layers: bool) -> Self {
+        self.hidden_layers = hidden_layers;
+        self
+    }
+
+    pub fn debug_dump(mut self, debug_dump: bool) -> Self {
+        self.debug_dump = debug_dump;
+        self
+    }
+
+    pub fn dpi(mut self, dpi: f32) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    pub fn background(mut self, css_color: &str) -> Self {
+        self.background = Some(css_color.to_string());
+        self
+    }
+}
+
+/// How a line is stroked, as decided by its brush.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrokeStyle {
+    /// A single stroke following all points, with the width of the last
+    /// point. Used by the highlighter and the fineliner.
+    ConstantWidth,
+    /// A filled outline whose width follows the points.
+    VariableWidth,
+}
+
+/// How an eraser line removes ink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EraseStyle {
+    /// Ink under the stroke is removed. The stroke is as wide as ink strokes
+    /// of the last point's width, see `WIDTH_FACTOR`. A single point removes
+    /// a dot.
+    Stroke,
+    /// Ink inside the polygon of the points is removed.
+    Area,
+}
+
+/// An output backend. `render_pages` walks the pages and calls these methods
+/// in order: for each page `begin_page`, then `begin_layer`, `draw_line` or
+/// `erase` for each line and `end_layer` for each visible layer, then
+/// `end_page`. `finish` is called after the last page.
+pub trait Renderer {
+    /// The options to render with, usually given to the backend's
+    /// constructor.
+    fn options(&self) -> &RenderOptions;
+
+    /// Starts a page. `template` is the name of the page's template, which
+    /// is always a key of `TEMPLATES`.
+    fn begin_page(&mut self, page: &Page, template: Option<&str>) -> Result<()>;
+
+    fn begin_layer(&mut self, _layer: &Layer) -> Result<()> {
+        Ok(())
+    }
+
+    /// Draws a line with at least one point in the given CSS color.
+    fn draw_line(&mut self, line: &Line, style: StrokeStyle, css_color: &str) -> Result<()>;
+
+    /// Removes the ink drawn before in the current layer under an eraser
+    /// line with at least one point. Backends that cannot erase ignore it.
+    fn erase(&mut self, _line: &Line, _style: EraseStyle) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_layer(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<()>;
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Renders pages with the given backend and its options. The template of
+/// the options overrides the templates of all pages, page templates we do
+/// not know are left out. Erasers remove ink drawn before them in their
+/// layer, selections are not drawn.
+pub fn render_pages(renderer: &mut dyn Renderer, pages: &[Page]) -> Result<()> {
+    let options = renderer.options().clone();
+    let template = options.template.as_deref();
+    if let Some(template) = template {
+        if !TEMPLATES.contains_key(template) {
+            return Err(Error::UnknownTemplate(template.to_string()));
+        }
+    }
+
+    for page in pages {
+        let template = template
+            .or(page.template.as_deref())
+            .filter(|template| TEMPLATES.contains_key(template));
+        renderer.begin_page(page, template)?;
+        for (layer_id, layer) in page.layers.iter().enumerate() {
+            if layer.hidden && !options.hidden_layers {
+                continue;
+            }
+            renderer.begin_layer(layer)?;
+            for line in layer.lines.iter() {
+                if line.points.is_empty() {
+                    continue;
+                }
+                let simplified_line;
+                let line = match options.simplify_tolerance {
+                    Some(tolerance) => {
+                        simplified_line = line.simplified(tolerance);
+                        &simplified_line
+                    }
+                    None => line,
+                };
+                let style = match line.brush_type {
+                    BrushType::Highlighter | BrushType::Fineliner | BrushType::Unknown(_) => {
+                        StrokeStyle::ConstantWidth
+                    }
+                    BrushType::Eraser => {
+                        renderer.erase(line, EraseStyle::Stroke)?;
+                        continue;
+                    }
+                    BrushType::EraseArea => {
+                        renderer.erase(line, EraseStyle::Area)?;
+                        continue;
+                    }
+                    BrushType::EraseAll | BrushType::SelectionBrush => continue,
+                    _ => StrokeStyle::VariableWidth,
+                };
+                let css_color = line_to_css_color(line, layer_id, &options.layer_colors);
+                renderer.draw_line(line, style, &css_color)?;
+            }
+            renderer.end_layer()?;
+        }
+        renderer.end_page()?;
+    }
+    renderer.finish()
+}
+
+/// Size of the rendered page and the transformation from the coordinates
+/// of the .rm files to the rendered page. Landscape pages are turned a
+/// quarter clockwise and get the page size swapped.
+pub(crate) fn page_transform(page: &Page, options: &RenderOptions) -> (f32, f32, Matrix) {
+    let (width, height) = (options.page_width, options.page_height);
+    match page.orientation {
+        Orientation::Portrait => (width, height, Matrix::identity()),
+        Orientation::Landscape => (
+            height,
+            width,
+            // Matrix::rotate(FRAC_PI_2), without rounding errors.
+            Matrix::translate(height, 0.0) * Matrix([0.0, 1.0, -1.0, 0.0, 0.0, 0.0]),
+        ),
+    }
+}
+
+/// Axis-aligned bounds of points, taking the point widths into account.
+/// Starts out empty, with infinite minimum and negative infinite maximum
+/// coordinates, and grows with each point enclosed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
     pub min_x: f32,
     pub min_y: f32,
     pub max_x: f32,
     pub max_y: f32,
 }
 
+impl Default for BoundingBox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BoundingBox {
     pub fn new() -> BoundingBox {
         BoundingBox {
-            min_x: INFINITY,
-            min_y: INFINITY,
-            max_x: NEG_INFINITY,
-            max_y: NEG_INFINITY,
+            min_x: f32::INFINITY,
+            min_y: f32::INFINITY,
+            max_x: f32::NEG_INFINITY,
+            max_y: f32::NEG_INFINITY,
         }
     }
 
+    /// Whether nothing has been enclosed yet.
+    pub fn is_empty(&self) -> bool {
+        self.min_x > self.max_x || self.min_y > self.max_y
+    }
+
+    pub fn width(&self) -> f32 {
+        (self.max_x - self.min_x).max(0.0)
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.max_y - self.min_y).max(0.0)
+    }
+
+    /// The box enclosing this box after the transformation.
+    pub fn transformed(&self, matrix: Matrix) -> BoundingBox {
+        if self.is_empty() {
+            return *self;
+        }
+        let corners = [
+            [self.min_x, self.min_y],
+            [self.max_x, self.min_y],
+            [self.max_x, self.max_y],
+            [self.min_x, self.max_y],
+        ];
+        corners
+            .iter()
+            .fold(BoundingBox::new(), |mut bounding_box, &corner| {
+                let [x, y] = matrix * corner;
+                bounding_box.min_x = bounding_box.min_x.min(x);
+                bounding_box.min_y = bounding_box.min_y.min(y);
+                bounding_box.max_x = bounding_box.max_x.max(x);
+                bounding_box.max_y = bounding_box.max_y.max(y);
+                bounding_box
+            })
+    }
+
+    /// Grows the box to enclose another one.
+    pub fn enclose(mut self, other: &BoundingBox) -> BoundingBox {
+        self.min_x = self.min_x.min(other.min_x);
+        self.min_y = self.min_y.min(other.min_y);
+        self.max_x = self.max_x.max(other.max_x);
+        self.max_y = self.max_y.max(other.max_y);
+        self
+    }
+
+    /// Grows the box to enclose a circle with the width of the point.
     pub fn enclose_point(mut self, point: &Point) -> BoundingBox {
         let radius = 0.5 * point.width;
         self.min_x = self.min_x.min(point.x - radius);
@@ -34,76 +356,518 @@ impl BoundingBox {
         self
     }
 
+    pub fn enclose_layer(mut self, layer: &Layer) -> BoundingBox {
+        for line in layer.lines.iter() {
+            self = self.enclose_line(line);
+        }
+        self
+    }
+
     pub fn enclose_page(mut self, page: &Page) -> BoundingBox {
         for layer in page.layers.iter() {
-            for line in layer.lines.iter() {
-                self = self.enclose_line(line);
-            }
+            self = self.enclose_layer(layer);
         }
         self
     }
 }
 
+#[test]
+fn test_bounding_box() {
+    let line = Line::with_points(
+        Point {
+            width: 2.0,
+            ..Default::default()
+        },
+        &[(10.0, 20.0), (30.0, 5.0)],
+    );
+    let bounding_box = BoundingBox::new();
+    assert!(bounding_box.is_empty());
+    assert_eq!(bounding_box.width(), 0.0);
+
+    let bounding_box = bounding_box.enclose_line(&line);
+    assert!(!bounding_box.is_empty());
+    assert_eq!(
+        bounding_box,
+        BoundingBox {
+            min_x: 9.0,
+            min_y: 4.0,
+            max_x: 31.0,
+            max_y: 21.0
+        }
+    );
+    assert_eq!((bounding_box.width(), bounding_box.height()), (22.0, 17.0));
+
+    let layer = Layer {
+        lines: vec![line],
+        ..Default::default()
+    };
+    let page = Page {
+        layers: vec![layer],
+        ..Default::default()
+    };
+    assert_eq!(BoundingBox::new().enclose_page(&page), bounding_box);
+    assert_eq!(BoundingBox::new().enclose(&bounding_box), bounding_box);
+
+    let (width, height, matrix) = page_transform(
+        &Page {
+            orientation: Orientation::Landscape,
+            ..Default::default()
+        },
+        &RenderOptions::new(),
+    );
+    assert_eq!((width, height), (PAGE_HEIGHT, PAGE_WIDTH));
+    let rotated = bounding_box.transformed(matrix);
+    assert_eq!(
+        [rotated.min_x, rotated.min_y, rotated.max_x, rotated.max_y].map(f32::round),
+        [PAGE_HEIGHT - 21.0, 9.0, PAGE_HEIGHT - 4.0, 31.0]
+    );
+}
+
 pub fn line_to_css_color(line: &Line, layer_idx: usize, layer_colors: &[LayerColor]) -> String {
+    // The exact color only replaces the colors that layer colors cannot set.
+    if let (Some(argb), Color::Highlight | Color::Unknown(_)) = (line.argb, line.color) {
+        return format!("#{:06x}", argb & 0xff_ffff);
+    }
     // If no layer color is provided for this layer, default to the last layer we have colors for.
-    let layer_colors = layer_colors.get(layer_idx).cloned().unwrap_or_default();
-    match line.brush_type {
-        BrushType::Highlighter => "rgb(240, 220, 40)".to_string(),
-        _ => match line.color {
-            Color::Black => layer_colors.black,
-            Color::Grey => layer_colors.grey,
-            Color::White => layer_colors.white,
-            Color::Blue => layer_colors.blue,
-            Color::Red => layer_colors.red,
-        },
+    let layer_colors = layer_colors
+        .get(layer_idx)
+        .or(layer_colors.last())
+        .cloned()
+        .unwrap_or_default();
+    match (line.brush_type, line.color) {
+        // Older firmware had a single highlighter color.
+        (
+            BrushType::Highlighter,
+            Color::Black | Color::Grey | Color::White | Color::Blue | Color::Red,
+        ) => layer_colors.highlight,
+        (_, Color::Black | Color::Unknown(_)) => layer_colors.black,
+        (_, Color::Grey | Color::GreyOverlap) => layer_colors.grey,
+        (_, Color::White) => layer_colors.white,
+        (_, Color::Blue) => layer_colors.blue,
+        (_, Color::Red) => layer_colors.red,
+        (_, Color::Yellow | Color::LightYellow) => layer_colors.yellow,
+        (_, Color::Green | Color::LightGreen) => layer_colors.green,
+        (_, Color::Pink) => layer_colors.pink,
+        (_, Color::Cyan) => layer_colors.cyan,
+        (_, Color::Magenta) => layer_colors.magenta,
+        (_, Color::Highlight) => layer_colors.highlight,
     }
 }
 
-/// Creates a vector of quadrilateral coordinates enclosing each segment of the
-/// line. The length of the returned vector is always a multiple of 8 (4 points
-/// à 2 coordinates per quadrilateral.)
-pub(crate) fn segment_quads(line: &Line) -> Vec<f32> {
-    let points = &line.points;
-    let offset_distance = if points.is_empty() {
-        0.0
+/// Opacity of the line segment ending in `point`. Ballpoint pen and second
+/// generation pencil strokes get lighter with decreasing pressure, the first
+/// generation brushes draw with constant opacity.
+pub(crate) fn segment_opacity(line: &Line, point: &Point) -> f32 {
+    let opacity = match (line.brush_type, line.brush_generation()) {
+        (BrushType::BallPoint, _) => point.pressure.powf(5.0) + 0.7,
+        (BrushType::TiltPencil | BrushType::SharpPencil, Some(2)) => 0.4 + 0.6 * point.pressure,
+        _ => 1.0,
+    };
+    opacity * color_opacity(line)
+}
+
+/// Opacity given by the alpha of the line's ARGB color, 1 for lines without
+/// one.
+pub(crate) fn color_opacity(line: &Line) -> f32 {
+    line.argb.map_or(1.0, |argb| (argb >> 24) as f32 / 255.0)
+}
+
+/// Converts a CSS color as used in `LayerColor` to RGB components in the
+/// range 0 to 1. Supports `#rgb`, `#rrggbb`, `rgb(r, g, b)` and the basic
+/// color keywords. Anything else is treated as black.
+pub fn css_color_to_rgb(css_color: &str) -> [f32; 3] {
+    let css_color = css_color.trim().to_lowercase();
+    let rgb: Option<[u8; 3]> = if let Some(hex) = css_color.strip_prefix('#') {
+        let digits: Option<Vec<u8>> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect();
+        match digits.as_deref() {
+            Some(&[r, g, b]) => Some([r * 17, g * 17, b * 17]),
+            Some(&[r1, r2, g1, g2, b1, b2]) => Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+            _ => None,
+        }
+    } else if let Some(components) = css_color
+        .strip_prefix("rgb(")
+        .and_then(|c| c.strip_suffix(')'))
+    {
+        let components: Option<Vec<u8>> = components
+            .split(',')
+            .map(|c| c.trim().parse().ok())
+            .collect();
+        components.and_then(|c| c.try_into().ok())
     } else {
-        points[0].width * 0.5
+        match css_color.as_str() {
+            "white" => Some([255, 255, 255]),
+            "silver" => Some([192, 192, 192]),
+            "gray" | "grey" => Some([128, 128, 128]),
+            "red" => Some([255, 0, 0]),
+            "maroon" => Some([128, 0, 0]),
+            "yellow" => Some([255, 255, 0]),
+            "olive" => Some([128, 128, 0]),
+            "lime" => Some([0, 255, 0]),
+            "green" => Some([0, 128, 0]),
+            "aqua" | "cyan" => Some([0, 255, 255]),
+            "teal" => Some([0, 128, 128]),
+            "blue" => Some([0, 0, 255]),
+            "navy" => Some([0, 0, 128]),
+            "fuchsia" | "magenta" => Some([255, 0, 255]),
+            "purple" => Some([128, 0, 128]),
+            _ => None,
+        }
     };
+    let [r, g, b] = rgb.unwrap_or([0, 0, 0]);
+    [r, g, b].map(|c| f32::from(c) / 255.0)
+}
 
-    let offsets = line.offsets(offset_distance);
-
-    offsets.iter().enumerate().fold(
-        Vec::with_capacity(8 * (points.len() - 1)),
-        |mut coords, (i, offset)| {
-            let p1 = &points[i];
-            let p2 = &points[i + 1];
-
-            coords.extend_from_slice(&[
-                p1.x + offset.x,
-                p1.y + offset.y,
-                p2.x + offset.x,
-                p2.y + offset.y,
-                p2.x - offset.x,
-                p2.y - offset.y,
-                p1.x - offset.x,
-                p1.y - offset.y,
-            ]);
-            coords
-        },
-    )
+#[test]
+fn test_css_color_to_rgb() {
+    assert_eq!(css_color_to_rgb("black"), [0.0, 0.0, 0.0]);
+    assert_eq!(css_color_to_rgb("#fff"), [1.0, 1.0, 1.0]);
+    assert_eq!(css_color_to_rgb("#FF0033"), [1.0, 0.0, 0.2]);
+    assert_eq!(css_color_to_rgb("rgb(0, 51, 255)"), [0.0, 0.2, 1.0]);
+    assert_eq!(css_color_to_rgb("no color"), [0.0, 0.0, 0.0]);
 }
 
 #[test]
-fn test_segment_quads() {
+fn test_line_to_css_color() {
+    let layer_colors = [LayerColor {
+        green: "green".to_string(),
+        highlight: "yellow".to_string(),
+        ..Default::default()
+    }];
+    let line = |brush_type, color, argb| Line {
+        brush_type,
+        color,
+        argb,
+        ..Default::default()
+    };
+    let css_color = |line: &Line| line_to_css_color(line, 0, &layer_colors);
+
+    assert_eq!(
+        css_color(&line(BrushType::Fineliner, Color::Green, None)),
+        "green"
+    );
+    assert_eq!(
+        css_color(&line(BrushType::Highlighter, Color::Black, None)),
+        "yellow"
+    );
+    // Layers without colors use those of the last layer that has some.
+    let green_line = line(BrushType::Fineliner, Color::Green, None);
+    assert_eq!(line_to_css_color(&green_line, 3, &layer_colors), "green");
+    assert_eq!(
+        line_to_css_color(&green_line, 0, &[]),
+        LayerColor::default().green
+    );
+    assert_eq!(
+        css_color(&line(BrushType::Highlighter, Color::LightGreen, None)),
+        "green"
+    );
+
+    // The exact color and its alpha win over the highlight color, but not
+    // over the colors of the layer.
+    let highlight_line = line(
+        BrushType::Highlighter,
+        Color::Highlight,
+        Some(0x80_a1_d8_7d),
+    );
+    assert_eq!(css_color(&highlight_line), "#a1d87d");
+    assert_eq!(color_opacity(&highlight_line), 128.0 / 255.0);
+    assert_eq!(
+        css_color(&line(
+            BrushType::Fineliner,
+            Color::Green,
+            Some(0xff_00_ff_00)
+        )),
+        "green"
+    );
+    assert_eq!(
+        css_color(&line(
+            BrushType::Fineliner,
+            Color::Unknown(42),
+            Some(0xff_12_34_56)
+        )),
+        "#123456"
+    );
+}
+
+/// Opacity of a whole line, the average opacity of its segments.
+pub(crate) fn line_opacity(line: &Line) -> f32 {
+    let segment_points = &line.points[line.points.len().min(1)..];
+    if segment_points.is_empty() {
+        return line
+            .points
+            .first()
+            .map_or(1.0, |p| segment_opacity(line, p));
+    }
+    let total: f32 = segment_points
+        .iter()
+        .map(|p| segment_opacity(line, p))
+        .sum();
+    total / segment_points.len() as f32
+}
+
+#[test]
+fn test_segment_opacity() {
+    let point = Point {
+        pressure: 0.5,
+        ..Default::default()
+    };
+    let opacity = |brush_code: i32| {
+        let line = Line {
+            brush_type: BrushType::from_code(brush_code),
+            brush_code: Some(brush_code),
+            ..Default::default()
+        };
+        segment_opacity(&line, &point)
+    };
+    // First generation pens and pencils ignore the pressure.
+    assert_eq!(opacity(2), 1.0);
+    assert_eq!(opacity(1), 1.0);
+    assert_eq!(opacity(7), 1.0);
+    assert_eq!(opacity(15), 0.5f32.powf(5.0) + 0.7);
+    assert_eq!(opacity(14), 0.4 + 0.6 * 0.5);
+    assert_eq!(opacity(13), opacity(14));
+}
+
+/// Coordinates of the line's points, leaving out points closer than
+/// `distance_threshold` to the previous point that is kept. The first and
+/// the last point are always kept.
+pub(crate) fn thinned_points(line: &Line, distance_threshold: f32) -> Vec<(f32, f32)> {
+    let mut point_iter = line.points.iter().enumerate();
+    let mut prev_point = match point_iter.next() {
+        Some((_, p)) => p,
+        None => return Vec::new(),
+    };
+
+    let mut points = vec![(prev_point.x, prev_point.y)];
+    for (idx, point) in point_iter {
+        let dist = point - prev_point;
+        let is_last_point = idx + 1 == line.points.len();
+        if dist.length() < distance_threshold && !is_last_point {
+            continue;
+        }
+        points.push((point.x, point.y));
+        prev_point = point;
+    }
+    points
+}
+
+/// Creates the outline polygon of a stroke whose width follows the width of
+/// each point, with round joins and end caps. Points closer than
+/// `distance_threshold` to their predecessor are skipped. The polygon may
+/// overlap itself and has to be filled with the nonzero rule.
+pub fn stroke_outline(line: &Line, distance_threshold: f32) -> Vec<(f32, f32)> {
+    let mut points: Vec<Point> = Vec::with_capacity(line.points.len());
+    for (idx, point) in line.points.iter().enumerate() {
+        let is_last_point = idx + 1 == line.points.len();
+        if let Some(prev_point) = points.last() {
+            let dist = prev_point.distance(point);
+            if dist == 0.0 || (dist < distance_threshold && !is_last_point) {
+                continue;
+            }
+        }
+        points.push(point.clone());
+    }
+    let line = Line {
+        points,
+        ..line.clone_attributes()
+    };
+    let points: Vec<&Point> = line.points.iter().collect();
+
+    let mut outline = Vec::new();
+    if points.len() < 2 {
+        if let Some(point) = points.first() {
+            outline_arc(&mut outline, point, 0.0, 2.0 * PI);
+        }
+        return outline;
+    }
+
+    // Unit normals to the left of each segment.
+    let normals: Vec<(f32, f32)> = line
+        .offsets(1.0)
+        .into_iter()
+        .map(|normal| (normal.x, normal.y))
+        .collect();
+    // Going along one side, around the end, back along the other side is
+    // the same as going along the first side of the reversed stroke.
+    outline_side(&mut outline, &points, &normals);
+    let points: Vec<&Point> = points.into_iter().rev().collect();
+    let normals: Vec<(f32, f32)> = normals.into_iter().rev().map(|(x, y)| (-x, -y)).collect();
+    outline_side(&mut outline, &points, &normals);
+    outline
+}
+
+/// Appends the left side of the stroke, followed by the cap at its end.
+fn outline_side(outline: &mut Vec<(f32, f32)>, points: &[&Point], normals: &[(f32, f32)]) {
+    let offset = |point: &Point, (x, y): (f32, f32)| {
+        let radius = 0.5 * WIDTH_FACTOR * point.width;
+        (point.x + radius * x, point.y + radius * y)
+    };
+    outline.push(offset(points[0], normals[0]));
+    for (idx, normals) in normals.windows(2).enumerate() {
+        let (point, prev_normal, normal) = (points[idx + 1], normals[0], normals[1]);
+        let turn = prev_normal.0 * normal.1 - prev_normal.1 * normal.0;
+        let cos = prev_normal.0 * normal.0 + prev_normal.1 * normal.1;
+        if cos > (PI / 8.0).cos() {
+            // Slight bends, as in most of a stroke, get a single miter point.
+            let (x, y) = (prev_normal.0 + normal.0, prev_normal.1 + normal.1);
+            let miter_factor = 2.0 / (x * x + y * y);
+            outline.push(offset(point, (x * miter_factor, y * miter_factor)));
+            continue;
+        }
+        outline.push(offset(point, prev_normal));
+        if turn > 0.0 {
+            // The stroke turns towards this side, the segment outlines
+            // overlap. Going through the point keeps the gap between them
+            // filled.
+            outline.push((point.x, point.y));
+        } else {
+            outline_arc(
+                outline,
+                point,
+                prev_normal.1.atan2(prev_normal.0),
+                turn.atan2(cos),
+            );
+        }
+        outline.push(offset(point, normal));
+    }
+    let (last_point, &(x, y)) = (points[points.len() - 1], &normals[normals.len() - 1]);
+    outline.push(offset(last_point, (x, y)));
+    outline_arc(outline, last_point, y.atan2(x), -PI);
+}
+
+/// Appends an arc around the point with the radius of its stroke, leaving
+/// out the end points.
+fn outline_arc(outline: &mut Vec<(f32, f32)>, center: &Point, start: f32, sweep: f32) {
+    let radius = 0.5 * WIDTH_FACTOR * center.width;
+    let steps = (sweep.abs() / (PI / 8.0)).ceil() as usize;
+    outline.extend((1..steps).map(|step| {
+        let angle = start + sweep * step as f32 / steps as f32;
+        (
+            center.x + radius * angle.cos(),
+            center.y + radius * angle.sin(),
+        )
+    }));
+}
+
+#[test]
+fn test_stroke_outline() {
     let line = Line::with_points(
         Point {
             width: 10.0,
             ..Default::default()
         },
-        &vec![(0.0, 0.0), (3.0, 4.0), (6.0, 4.0)][..],
+        &[(0.0, 0.0), (0.5, 0.0), (10.0, 0.0), (10.0, 10.0)],
     );
+    let radius = 0.5 * WIDTH_FACTOR * 10.0;
+    let outline = stroke_outline(&line, 2.0);
+    let distance =
+        |x: f32, y: f32, (px, py): (f32, f32)| ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+    // All of the outline is at most one radius away from the stroke.
+    for &point in &outline {
+        let from_stroke = distance(point.0.clamp(0.0, 10.0), 0.0, point).min(distance(
+            10.0,
+            point.1.clamp(0.0, 10.0),
+            point,
+        ));
+        assert!(from_stroke < radius + 1e-3, "{:?}", point);
+    }
+    // Both caps and the outer join are round.
+    assert!(outline
+        .iter()
+        .any(|&p| (p.0 + radius).abs() < 1e-3 && p.1.abs() < 1e-3));
+    assert!(outline
+        .iter()
+        .any(|&p| (p.0 - 10.0).abs() < 1e-3 && (p.1 - 10.0 - radius).abs() < 1e-3));
+    assert!(outline
+        .iter()
+        .any(|&p| (distance(10.0, 0.0, p) - radius).abs() < 1e-3 && p.0 > 10.0 && p.1 < 0.0));
+    // The inner join goes through the corner.
+    assert!(outline.contains(&(10.0, 0.0)));
+    // The point closer than the distance threshold is skipped.
+    assert!(!outline.contains(&(0.5, radius)));
+}
+
+#[test]
+fn test_render_pages() {
+    struct Recorder(Vec<String>, RenderOptions);
+    impl Renderer for Recorder {
+        fn options(&self) -> &RenderOptions {
+            &self.1
+        }
+        fn begin_page(&mut self, _page: &Page, template: Option<&str>) -> Result<()> {
+            self.0.push(format!("page {:?}", template));
+            Ok(())
+        }
+        fn begin_layer(&mut self, layer: &Layer) -> Result<()> {
+            self.0.push(format!("layer {:?}", layer.name));
+            Ok(())
+        }
+        fn draw_line(&mut self, line: &Line, style: StrokeStyle, css_color: &str) -> Result<()> {
+            self.0
+                .push(format!("{:?} {:?} {}", line.brush_type, style, css_color));
+            Ok(())
+        }
+        fn erase(&mut self, _line: &Line, style: EraseStyle) -> Result<()> {
+            self.0.push(format!("erase {:?}", style));
+            Ok(())
+        }
+        fn end_page(&mut self) -> Result<()> {
+            self.0.push("end".to_string());
+            Ok(())
+        }
+    }
+
+    let line = |brush_type| Line {
+        brush_type,
+        points: vec![Point::default()],
+        ..Default::default()
+    };
+    let pages = [
+        Page {
+            layers: vec![
+                Layer {
+                    lines: vec![
+                        line(BrushType::Fineliner),
+                        line(BrushType::Eraser),
+                        line(BrushType::Marker),
+                    ],
+                    name: Some("Visible".to_string()),
+                    ..Default::default()
+                },
+                Layer {
+                    lines: vec![line(BrushType::Marker)],
+                    hidden: true,
+                    ..Default::default()
+                },
+            ],
+            template: Some("P Grid medium".to_string()),
+            ..Default::default()
+        },
+        Page {
+            template: Some("No template".to_string()),
+            ..Default::default()
+        },
+    ];
+
+    let mut recorder = Recorder(Vec::new(), RenderOptions::new());
+    render_pages(&mut recorder, &pages).unwrap();
     assert_eq!(
-        segment_quads(&line),
-        vec![-4.0, 3.0, -1.0, 7.0, 7.0, 1.0, 4.0, -3.0, 3.0, 9.0, 6.0, 9.0, 6.0, -1.0, 3.0, -1.0]
+        recorder.0,
+        vec![
+            "page Some(\"P Grid medium\")",
+            "layer Some(\"Visible\")",
+            "Fineliner ConstantWidth black",
+            "erase Stroke",
+            "Marker VariableWidth black",
+            "end",
+            "page None",
+            "end",
+        ]
     );
+
+    let options = RenderOptions::new().template("No template");
+    let mut recorder = Recorder(Vec::new(), options);
+    assert!(render_pages(&mut recorder, &pages).is_err());
 }
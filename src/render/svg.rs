@@ -1,34 +1,32 @@
-use crate::render::renderlib::{line_to_css_color, BoundingBox};
+use crate::render::curves::{path_segments, PathSegment};
+use crate::render::renderlib::{
+    color_opacity, line_opacity, page_transform, render_pages, stroke_outline, thinned_points,
+    BoundingBox, EraseStyle, RenderOptions, Renderer, StrokeStyle, HIGHLIGHTER_OPACITY,
+    WIDTH_FACTOR,
+};
 use crate::render::templates;
-use crate::{BrushType, LayerColor, Line, Page, Result};
+use crate::{BrushType, Error, Layer, Line, Matrix, Page, Result};
 use std::io;
+use svg::node::element::path::Data;
+use svg::node::element::{Group, Mask, Path, Rectangle};
+use svg::Node;
 
-const WIDTH_FACTOR: f32 = 0.8;
+/// Lets Inkscape (and other editors) show the layer groups as named layers.
+const INKSCAPE_NAMESPACE: &str = "http://www.inkscape.org/namespaces/inkscape";
 
 pub fn render_constant_width_line(
     line: &Line,
     css_color: &str,
     distance_threshold: f32,
+    curve_tolerance: Option<f32>,
     debug_dump: bool,
 ) -> svg::node::element::Path {
-    let mut point_iter = line.points.iter().enumerate();
-
-    let mut prev_point = if let Some((_, p)) = point_iter.next() {
-        p
-    } else {
-        return svg::node::element::Path::new();
+    let last_point = match line.points.last() {
+        Some(p) => p,
+        None => return svg::node::element::Path::new(),
     };
-
-    let mut data = svg::node::element::path::Data::new().move_to((prev_point.x, prev_point.y));
-    for (idx, point) in point_iter {
-        let dist = point - prev_point;
-        let is_last_point = idx + 1 == line.points.len();
-        if dist.length() < distance_threshold && !is_last_point {
-            continue;
-        }
-        data = data.line_to((point.x, point.y));
-        prev_point = point;
-    }
+    let points = thinned_points(line, distance_threshold);
+    let data = path_data(&points, curve_tolerance);
 
     let mut path = svg::node::element::Path::new()
         .set("fill", "none")
@@ -41,14 +39,18 @@ pub fn render_constant_width_line(
     match line.brush_type {
         BrushType::Highlighter => {
             path = path
-                .set("stroke-width", prev_point.width)
+                .set("stroke-width", last_point.width)
                 .set("stroke-linecap", "butt")
-                .set("stroke-opacity", 0.25f32);
+                .set("stroke-opacity", HIGHLIGHTER_OPACITY * color_opacity(line));
         }
         _ => {
             path = path
-                .set("stroke-width", prev_point.width * WIDTH_FACTOR)
+                .set("stroke-width", last_point.width * WIDTH_FACTOR)
                 .set("stroke-linecap", "round");
+            let opacity = color_opacity(line);
+            if opacity < 1.0 {
+                path = path.set("stroke-opacity", opacity);
+            }
         }
     }
 
@@ -59,137 +61,304 @@ pub fn render_constant_width_line(
     path
 }
 
+/// Renders the line as a single filled outline whose width follows the
+/// points.
 pub fn render_variable_width_line(
     line: &Line,
     css_color: &str,
     distance_threshold: f32,
+    curve_tolerance: Option<f32>,
     debug_dump: bool,
-) -> svg::node::element::Group {
-    let mut stroke_group = svg::node::element::Group::new()
-        .set("fill", "none")
+) -> svg::node::element::Path {
+    let outline = stroke_outline(line, distance_threshold);
+    if outline.is_empty() {
+        return svg::node::element::Path::new();
+    }
+    let data = path_data(&outline, curve_tolerance);
+
+    let mut path = svg::node::element::Path::new()
         .set("color", css_color)
-        .set("stroke", "currentColor")
-        .set("stroke-linecap", "round")
-        .set("class", format!("{:#?}", line.brush_type));
+        .set("fill", "currentColor")
+        .set("class", format!("{:#?}", line.brush_type))
+        .set("d", data.close());
+    let opacity = line_opacity(line);
+    if opacity < 1.0 {
+        path = path.set("fill-opacity", opacity);
+    }
 
-    let mut point_iter = line.points.iter().enumerate();
+    if debug_dump {
+        path = path.add(tooltip(&format!("{:#?}", line)));
+    }
 
-    let mut prev_point = if let Some((_, p)) = point_iter.next() {
-        p
-    } else {
-        return svg::node::element::Group::new();
-    };
+    path
+}
 
-    for (idx, point) in point_iter {
-        let dist = point - prev_point;
-        let is_last_point = idx + 1 == line.points.len();
-        if dist.length() < distance_threshold && !is_last_point {
-            continue;
+/// Creates path data through the points, optionally fitting curves to them.
+fn path_data(points: &[(f32, f32)], curve_tolerance: Option<f32>) -> Data {
+    let mut data = Data::new();
+    if let Some(&first_point) = points.first() {
+        data = data.move_to(first_point);
+    }
+    for segment in path_segments(points, curve_tolerance) {
+        data = match segment {
+            PathSegment::Line(point) => data.line_to(point),
+            PathSegment::Curve(c1, c2, point) => data.cubic_curve_to((c1, c2, point)),
+        };
+    }
+    data
+}
+
+/// Renders a page to SVG. The template of the options overrides the page's
+/// own template, see `Page::template`. Page templates we do not know are
+/// left out.
+pub fn render_svg(output: &mut dyn io::Write, page: &Page, options: &RenderOptions) -> Result<()> {
+    let mut renderer = SvgRenderer::new(output, options);
+    render_pages(&mut renderer, std::slice::from_ref(page))
+}
+
+/// Renders a page as a standalone SVG document. Layers become Inkscape
+/// layer groups. Beginning a second page fails with `Error::MultiplePages`.
+pub struct SvgRenderer<'a> {
+    output: &'a mut dyn io::Write,
+    options: &'a RenderOptions,
+    doc: svg::Document,
+    layer_group: Group,
+    layer_name: Option<String>,
+    template: Option<&'static str>,
+    /// SVG transform turning landscape pages, `None` for portrait pages.
+    page_transform: Option<String>,
+    mask_count: usize,
+    /// Mask of the erasers since the last line, applied to the ink before
+    /// the next line is drawn.
+    eraser_mask: Option<Mask>,
+    page_begun: bool,
+}
+
+impl<'a> SvgRenderer<'a> {
+    pub fn new(output: &'a mut dyn io::Write, options: &'a RenderOptions) -> Self {
+        SvgRenderer {
+            output,
+            options,
+            doc: svg::Document::new(),
+            layer_group: Group::new(),
+            layer_name: None,
+            template: None,
+            page_transform: None,
+            mask_count: 0,
+            eraser_mask: None,
+            page_begun: false,
+        }
+    }
+
+    /// Moves the ink drawn so far into a group masked by the pending
+    /// erasers. Consecutive erasers share a mask, and each mask wraps the
+    /// group of the previous one with the ink drawn since.
+    fn apply_eraser_mask(&mut self) {
+        if let Some(mask) = self.eraser_mask.take() {
+            let id = format!("eraser-{}", self.mask_count);
+            let ink = std::mem::replace(&mut self.layer_group, Group::new());
+            let masked_ink = Group::new().set("mask", format!("url(#{})", id)).add(ink);
+            self.layer_group = Group::new().add(mask).add(masked_ink);
         }
+    }
+}
 
-        let data = svg::node::element::path::Data::new()
-            .move_to((prev_point.x, prev_point.y))
-            .line_to((point.x, point.y));
+impl Renderer for SvgRenderer<'_> {
+    fn options(&self) -> &RenderOptions {
+        self.options
+    }
 
-        prev_point = point;
+    fn begin_page(&mut self, page: &Page, template: Option<&str>) -> Result<()> {
+        if self.page_begun {
+            return Err(Error::MultiplePages("SVG"));
+        }
+        self.page_begun = true;
+        let mut doc = svg::Document::new().set("xmlns:inkscape", INKSCAPE_NAMESPACE);
+        let (width, height, matrix) = page_transform(page, self.options);
+        if self.options.auto_crop {
+            let BoundingBox {
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+            } = BoundingBox::new().enclose_page(page).transformed(matrix);
+            let width = max_x - min_x;
+            let height = max_y - min_y;
+            doc = doc
+                .set("viewBox", (min_x, min_y, width, height))
+                .set("width", width)
+                .set("height", height);
+        } else {
+            doc = doc
+                .set("viewBox", (0i8, 0i8, width, height))
+                .set("width", width)
+                .set("height", height);
+        }
+        self.page_transform = (matrix != Matrix::identity()).then(|| {
+            let [a, b, c, d, e, f] = matrix.0;
+            format!("matrix({} {} {} {} {} {})", a, b, c, d, e, f)
+        });
+        if self.options.debug_dump {
+            doc = add_debug_style(doc);
+        }
+        self.doc = doc;
+        self.template = template.map(templates::template_snippet).transpose()?;
+        Ok(())
+    }
 
-        let opacity = match line.brush_type {
-            BrushType::BallPoint => point.pressure.powf(5.0) + 0.7,
-            _ => 1.0,
+    fn begin_layer(&mut self, layer: &Layer) -> Result<()> {
+        self.layer_group = Group::new();
+        self.layer_name = layer.name.clone();
+        Ok(())
+    }
+
+    fn draw_line(&mut self, line: &Line, style: StrokeStyle, css_color: &str) -> Result<()> {
+        self.apply_eraser_mask();
+        let (distance_threshold, curve_tolerance, debug_dump) = (
+            self.options.distance_threshold,
+            self.options.curve_tolerance,
+            self.options.debug_dump,
+        );
+        match style {
+            StrokeStyle::ConstantWidth => self.layer_group.append(render_constant_width_line(
+                line,
+                css_color,
+                distance_threshold,
+                curve_tolerance,
+                debug_dump,
+            )),
+            StrokeStyle::VariableWidth => self.layer_group.append(render_variable_width_line(
+                line,
+                css_color,
+                distance_threshold,
+                curve_tolerance,
+                debug_dump,
+            )),
         };
+        Ok(())
+    }
 
-        let mut path = svg::node::element::Path::new()
-            .set("stroke-width", point.width * WIDTH_FACTOR)
-            .set("d", data);
-        if opacity < 1.0 {
-            path = path.set("stroke-opacity", opacity);
+    /// Adds the eraser to the mask of the ink drawn so far, see
+    /// `SvgRenderer::apply_eraser_mask`.
+    fn erase(&mut self, line: &Line, style: EraseStyle) -> Result<()> {
+        if self.eraser_mask.is_none() {
+            self.mask_count += 1;
+            let id = format!("eraser-{}", self.mask_count);
+            self.eraser_mask = Some(eraser_mask(&id, self.options));
         }
-
-        if debug_dump {
-            path = path.add(tooltip(&format!("{:#?}\n{:#?}", prev_point, point)));
+        if let (Some(mask), Some(eraser)) = (self.eraser_mask.as_mut(), eraser_shape(line, style)) {
+            mask.append(eraser);
         }
+        Ok(())
+    }
 
-        stroke_group = stroke_group.add(path);
+    fn end_layer(&mut self) -> Result<()> {
+        self.apply_eraser_mask();
+        let mut layer_group = std::mem::replace(&mut self.layer_group, Group::new())
+            .set("class", "layer")
+            .set("inkscape:groupmode", "layer");
+        if let Some(name) = self.layer_name.take() {
+            layer_group = layer_group.set("inkscape:label", xml_escape(&name));
+        }
+        if let Some(page_transform) = &self.page_transform {
+            layer_group = layer_group.set("transform", page_transform.as_str());
+        }
+        self.doc.append(layer_group);
+        Ok(())
     }
-    stroke_group
-}
 
-pub fn render_svg(
-    output: &mut dyn io::Write,
-    page: &Page,
-    auto_crop: bool,
-    layer_colors: &[LayerColor],
-    distance_threshold: f32,
-    template: Option<&str>,
-    debug_dump: bool,
-) -> Result<()> {
-    let mut doc = svg::Document::new();
-    for (layer_id, layer) in page.layers.iter().enumerate() {
-        let mut layer_group = svg::node::element::Group::new().set("class", "layer");
-        for line in layer.lines.iter() {
-            let css_color = line_to_css_color(line, layer_id, layer_colors);
-            match &line.brush_type {
-                BrushType::Highlighter | BrushType::Fineliner => {
-                    layer_group = layer_group.add(render_constant_width_line(
-                        line,
-                        &css_color,
-                        distance_threshold,
-                        debug_dump,
-                    ))
-                }
-                BrushType::EraseArea
-                | BrushType::Eraser
-                | BrushType::EraseAll
-                | BrushType::SelectionBrush => (),
-                _ => {
-                    layer_group = layer_group.add(render_variable_width_line(
-                        line,
-                        &css_color,
-                        distance_threshold,
-                        debug_dump,
-                    ))
-                }
+    fn end_page(&mut self) -> Result<()> {
+        let doc = std::mem::replace(&mut self.doc, svg::Document::new());
+        self.mask_count = 0;
+        if let Some(template_snippet) = self.template {
+            // We splice the template snippet into the generated SVG
+            let doc_str: String = doc.to_string();
+            let doc_body_start = doc_str.find(">").expect("Missing closing tag") + 1;
+            self.output
+                .write_all(&doc_str.as_bytes()[..doc_body_start])?;
+            match &self.page_transform {
+                Some(page_transform) => write!(
+                    self.output,
+                    "<g transform=\"{}\">{}</g>",
+                    page_transform, template_snippet
+                )?,
+                None => self.output.write_all(template_snippet.as_bytes())?,
             }
+            self.output
+                .write_all(&doc_str.as_bytes()[doc_body_start..])?;
+        } else {
+            svg::write(&mut self.output, &doc)?;
         }
-        doc = doc.add(layer_group);
-    }
-    if auto_crop {
-        let BoundingBox {
-            min_x,
-            min_y,
-            max_x,
-            max_y,
-        } = BoundingBox::new().enclose_page(page);
-        let width = max_x - min_x;
-        let height = max_y - min_y;
-        doc = doc
-            .set("viewBox", (min_x, min_y, width, height))
-            .set("width", width)
-            .set("height", height);
-    } else {
-        let width = 1404i16;
-        let height = 1872i16;
-        doc = doc
-            .set("viewBox", (0i8, 0i8, width, height))
-            .set("width", width)
-            .set("height", height);
+        Ok(())
     }
-    if debug_dump {
-        doc = add_debug_style(doc);
+}
+
+/// A mask that shows everything, to which the erasers are added. It covers
+/// the page and its surroundings, so that cropped pages are masked as well.
+fn eraser_mask(id: &str, options: &RenderOptions) -> Mask {
+    let (width, height) = (options.page_width, options.page_height);
+    let (x, y) = (-width, -height);
+    let (width, height) = (3.0 * width, 3.0 * height);
+
+    Mask::new()
+        .set("id", id)
+        .set("maskUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height)
+        .add(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", width)
+                .set("height", height)
+                .set("fill", "white"),
+        )
+}
+
+/// The black shape of the eraser line, which hides the ink in a mask. An
+/// eraser stroke of a single point is a dot. `None` for an empty line.
+fn eraser_shape(line: &Line, style: EraseStyle) -> Option<Path> {
+    let last_point = line.points.last()?;
+    let width = last_point.width * WIDTH_FACTOR;
+    if let (EraseStyle::Stroke, [point]) = (style, &line.points[..]) {
+        // A circle of two arcs.
+        let radius = 0.5 * width;
+        let data = Data::new()
+            .move_to((point.x - radius, point.y))
+            .elliptical_arc_by((radius, radius, 0, 1, 0, width, 0))
+            .elliptical_arc_by((radius, radius, 0, 1, 0, -width, 0))
+            .close();
+        return Some(Path::new().set("fill", "black").set("d", data));
     }
 
-    if let Some(template) = template {
-        // We splice the template snippet into the generated SVG
-        let template_snippet = templates::template_snippet(template)?;
-        let doc_str: String = doc.to_string();
-        let doc_body_start = doc_str.find(">").expect("Missing closing tag") + 1;
-        output.write_all(doc_str[..doc_body_start].as_bytes())?;
-        output.write_all(template_snippet.as_bytes())?;
-        output.write_all(doc_str[doc_body_start..].as_bytes())?;
-    } else {
-        svg::write(output, &doc)?;
+    let mut data = Data::new();
+    for (i, point) in line.points.iter().enumerate() {
+        data = if i == 0 {
+            data.move_to((point.x, point.y))
+        } else {
+            data.line_to((point.x, point.y))
+        };
     }
-    Ok(())
+    Some(match style {
+        EraseStyle::Stroke => Path::new()
+            .set("fill", "none")
+            .set("stroke", "black")
+            .set("stroke-width", width)
+            .set("stroke-linecap", "round")
+            .set("stroke-linejoin", "round")
+            .set("d", data),
+        EraseStyle::Area => Path::new().set("fill", "black").set("d", data.close()),
+    })
+}
+
+/// Escapes text for XML attribute values, which the svg crate writes as is.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 fn tooltip(tooltip_text: &str) -> svg::node::element::Title {
@@ -207,3 +376,139 @@ fn add_debug_style(svg: svg::node::element::SVG) -> svg::node::element::SVG {
     "#,
     ))
 }
+
+#[test]
+fn test_render_page_template() {
+    let page = Page {
+        template: Some("P Grid medium".to_string()),
+        ..Default::default()
+    };
+    let render = |template: Option<&str>| {
+        let mut options = RenderOptions::new();
+        options.template = template.map(str::to_string);
+        let mut output = Vec::new();
+        render_svg(&mut output, &page, &options).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let grid = templates::template_snippet("P Grid medium").unwrap();
+    let lines = templates::template_snippet("P Lines small").unwrap();
+    assert!(render(None).contains(grid));
+    assert!(render(Some("P Lines small")).contains(lines));
+    assert!(!render(Some("P Lines small")).contains(grid));
+
+    // An SVG document holds a single page.
+    let options = RenderOptions::new();
+    let mut output = Vec::new();
+    let mut renderer = SvgRenderer::new(&mut output, &options);
+    let pages = [Page::default(), Page::default()];
+    assert!(matches!(
+        render_pages(&mut renderer, &pages),
+        Err(Error::MultiplePages("SVG"))
+    ));
+}
+
+#[test]
+fn test_render_landscape_page() {
+    let page = Page {
+        layers: vec![Layer::default()],
+        template: Some("LS Grid margin large".to_string()),
+        orientation: crate::Orientation::Landscape,
+    };
+    let mut output = Vec::new();
+    render_svg(&mut output, &page, &RenderOptions::new()).unwrap();
+    let svg = String::from_utf8(output).unwrap();
+
+    assert!(svg.contains(r#"viewBox="0 0 1872 1404""#));
+    // The template and the layer are turned.
+    assert_eq!(
+        svg.matches(r#"transform="matrix(0 1 -1 0 1872 0)""#)
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_render_layer_name() {
+    let name = r#"A & <B> "c" 'd'"#;
+    let page = Page {
+        layers: vec![Layer {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    render_svg(&mut output, &page, &RenderOptions::new()).unwrap();
+    let svg = String::from_utf8(output).unwrap();
+
+    let document = resvg::usvg::roxmltree::Document::parse(&svg).unwrap();
+    let label = document
+        .descendants()
+        .find_map(|node| node.attribute((INKSCAPE_NAMESPACE, "label")));
+    assert_eq!(label, Some(name));
+}
+
+#[test]
+fn test_render_eraser_mask() {
+    let line = |brush_type| Line::with_brush(brush_type, 10.0, &[(100.0, 100.0), (300.0, 100.0)]);
+    let page = Page {
+        layers: vec![Layer {
+            lines: vec![
+                line(BrushType::Fineliner),
+                line(BrushType::Eraser),
+                line(BrushType::Marker),
+            ],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    render_svg(&mut output, &page, &RenderOptions::new()).unwrap();
+    let svg = String::from_utf8(output).unwrap();
+
+    // The fineliner stroke is masked, the marker stroke drawn after the
+    // eraser is not.
+    let mask = svg.find("<mask").unwrap();
+    let masked = svg.find(r#"<g mask="url(#eraser-1)">"#).unwrap();
+    let fineliner = svg.find(r#"class="Fineliner""#).unwrap();
+    let marker = svg.find(r#"class="Marker""#).unwrap();
+    let masked_end = masked + svg[masked..].find("</g>").unwrap();
+    assert!(mask < masked && masked < fineliner && fineliner < masked_end);
+    assert!(masked_end < marker);
+    // Erasers are as wide as ink strokes.
+    assert!(svg[mask..masked].contains(r#"stroke-width="8""#));
+}
+
+#[test]
+fn test_render_consecutive_erasers() {
+    let line = |brush_type| Line::with_brush(brush_type, 10.0, &[(100.0, 100.0), (300.0, 100.0)]);
+    let page = Page {
+        layers: vec![Layer {
+            lines: vec![
+                line(BrushType::Fineliner),
+                line(BrushType::Eraser),
+                Line::with_brush(BrushType::Eraser, 10.0, &[(200.0, 200.0)]),
+                line(BrushType::Marker),
+                line(BrushType::Eraser),
+            ],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    render_svg(&mut output, &page, &RenderOptions::new()).unwrap();
+    let svg = String::from_utf8(output).unwrap();
+
+    // The first two erasers share a mask, which the second mask wraps
+    // together with the marker stroke.
+    assert_eq!(svg.matches("<mask").count(), 2);
+    let first_mask = svg.find(r#"id="eraser-1""#).unwrap();
+    let second_mask = svg.find(r#"id="eraser-2""#).unwrap();
+    assert!(second_mask < first_mask);
+    let first_mask_end = first_mask + svg[first_mask..].find("</mask>").unwrap();
+    // The single point eraser is a dot.
+    let shapes = &svg[first_mask..first_mask_end];
+    assert_eq!(shapes.matches("<path").count(), 2);
+    assert!(shapes.contains(r#"fill="black""#));
+}
@@ -82,14 +82,15 @@ pub static TEMPLATES: phf::Map<&'static str, &str> = phf_map! {
     "Perspective2" => include_str!("../../templates/Perspective2.svg"),
 };
 
-pub fn template_snippet(template_name: &str) -> Result<&str> {
+pub fn template_snippet(template_name: &str) -> Result<&'static str> {
     TEMPLATES
         .get(template_name)
         .map(|template| {
             let svg_start_tag_start = template.find("<svg").expect("Missing svg tag");
-            let svg_start_tag_end = template[svg_start_tag_start..]
-                .find(">")
-                .expect("Missing svg end tag")
+            let svg_start_tag_end = svg_start_tag_start
+                + template[svg_start_tag_start..]
+                    .find('>')
+                    .expect("Missing svg end tag")
                 + 1;
             let svg_end = template.find("</svg>").expect("Missing svg closing tag");
 
@@ -97,3 +98,10 @@ pub fn template_snippet(template_name: &str) -> Result<&str> {
         })
         .ok_or(Error::UnknownTemplate(template_name.to_string()))
 }
+
+#[test]
+fn test_template_snippet() {
+    let snippet = template_snippet("P Grid medium").unwrap();
+    assert!(snippet.trim_start().starts_with("<g id=\"surface1\">"));
+    assert!(snippet.trim_end().ends_with("</g>"));
+}
@@ -0,0 +1,179 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io;
+
+use crate::{BrushType, Color, Error, Layer, Line, LinesData, Page, Point, Result};
+
+impl LinesData {
+    /// Writes `LinesData` in the .rm format of `self.version`, so that
+    /// `LinesData::parse` reads it back unchanged.
+    /// Currently, only versions 3 and 5 can be written. Both store a single
+    /// page per file.
+    pub fn write(&self, file: &mut dyn io::Write) -> Result<()> {
+        let version_string = match self.version {
+            3 | 5 => format!("reMarkable .lines file, version={}", self.version),
+            _ => return Err(Error::UnsupportedVersion(self.version.to_string())),
+        };
+        if self.pages.len() != 1 {
+            return Err(Error::UnsupportedPageCount(self.version, self.pages.len()));
+        }
+
+        // The header is padded with spaces to 43 bytes.
+        file.write_all(format!("{:<43}", version_string).as_bytes())?;
+
+        let mut writer = LinesDataWriter {
+            file,
+            version: self.version,
+        };
+        writer.write_page(&self.pages[0])
+    }
+}
+
+pub(crate) struct LinesDataWriter<'a> {
+    pub file: &'a mut dyn io::Write,
+    pub version: i32,
+}
+
+impl LinesDataWriter<'_> {
+    fn write_i32(&mut self, value: i32) -> Result<()> {
+        Ok(self.file.write_i32::<LittleEndian>(value)?)
+    }
+
+    fn write_f32(&mut self, value: f32) -> Result<()> {
+        Ok(self.file.write_f32::<LittleEndian>(value)?)
+    }
+
+    fn write_len(&mut self, len: usize) -> Result<()> {
+        self.write_i32(i32::try_from(len)?)
+    }
+
+    fn write_line(&mut self, line: &Line) -> Result<()> {
+        // Keep the codes the line was read with, unless its brush or color
+        // was changed since. Codes from 12 on only exist from version 5 on.
+        let brush_code = match line.brush_code {
+            Some(code)
+                if BrushType::from_code(code) == line.brush_type
+                    && (code < 12 || self.version >= 5) =>
+            {
+                code
+            }
+            _ => line.brush_type.code(self.version),
+        };
+        let color_code = match line.color_code {
+            Some(code) if Color::from_code(code) == line.color => code,
+            _ => line.color.into(),
+        };
+        self.write_i32(brush_code)?;
+        self.write_i32(color_code)?;
+        self.write_i32(line.unknown_line_attribute)?;
+        self.write_f32(line.brush_base_size)?;
+        if self.version >= 5 {
+            self.write_i32(line.unknown_line_attribute_2)?;
+        }
+        self.write_len(line.points.len())?;
+        line.points
+            .iter()
+            .try_for_each(|point| self.write_point(point))
+    }
+
+    fn write_point(&mut self, point: &Point) -> Result<()> {
+        self.write_f32(point.x)?;
+        self.write_f32(point.y)?;
+        self.write_f32(point.speed)?;
+        self.write_f32(point.direction)?;
+        self.write_f32(point.width)?;
+        self.write_f32(point.pressure)
+    }
+
+    fn write_layer(&mut self, layer: &Layer) -> Result<()> {
+        self.write_len(layer.lines.len())?;
+        layer
+            .lines
+            .iter()
+            .try_for_each(|line| self.write_line(line))
+    }
+
+    pub fn write_page(&mut self, page: &Page) -> Result<()> {
+        self.write_len(page.layers.len())?;
+        page.layers
+            .iter()
+            .try_for_each(|layer| self.write_layer(layer))
+    }
+}
+
+#[test]
+fn test_write_round_trip() {
+    for version in [3, 5] {
+        let lines_data = LinesData {
+            version,
+            pages: vec![Page {
+                layers: vec![
+                    Layer {
+                        lines: vec![Line {
+                            brush_type: BrushType::Highlighter,
+                            color: Color::Red,
+                            unknown_line_attribute_2: if version >= 5 { 7 } else { 0 },
+                            brush_base_size: 2.125,
+                            points: vec![
+                                Point {
+                                    x: 1.0,
+                                    y: 2.0,
+                                    speed: 3.0,
+                                    direction: 4.0,
+                                    width: 5.0,
+                                    pressure: 0.5,
+                                },
+                                Point::default(),
+                            ],
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    Layer::default(),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut data = Vec::new();
+        lines_data.write(&mut data).unwrap();
+        assert_eq!(
+            data.len(),
+            43 + 4 + 4 + 16 + if version >= 5 { 4 } else { 0 } + 4 + 2 * 24 + 4
+        );
+
+        let parsed = LinesData::parse(&mut &data[..]).unwrap();
+        assert_eq!(parsed.version, version);
+        let mut rewritten = Vec::new();
+        parsed.write(&mut rewritten).unwrap();
+        assert_eq!(data, rewritten);
+    }
+
+    // A first generation marker in a version 5 file keeps its code.
+    let mut data = b"reMarkable .lines file, version=5          ".to_vec();
+    for value in [1i32, 1, 3, 0, 0, 0, 0, 0] {
+        // layers, lines, brush, color, unknown, base size, unknown, points
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut parsed = LinesData::parse(&mut &data[..]).unwrap();
+    let line = &mut parsed.pages[0].layers[0].lines[0];
+    assert_eq!(line.brush_type, BrushType::Marker);
+    assert_eq!(line.brush_code, Some(3));
+    assert_eq!(line.brush_generation(), Some(1));
+    let mut rewritten = Vec::new();
+    parsed.write(&mut rewritten).unwrap();
+    assert_eq!(data, rewritten);
+
+    parsed.pages[0].layers[0].lines[0].brush_type = BrushType::Fineliner;
+    let mut rewritten = Vec::new();
+    parsed.write(&mut rewritten).unwrap();
+    assert_eq!(rewritten[51..55], 17i32.to_le_bytes());
+
+    // Version 3 files get the old code of second generation brushes.
+    let mut parsed = LinesData::parse(&mut &rewritten[..]).unwrap();
+    assert_eq!(parsed.pages[0].layers[0].lines[0].brush_code, Some(17));
+    parsed.version = 3;
+    let mut rewritten = Vec::new();
+    parsed.write(&mut rewritten).unwrap();
+    assert_eq!(rewritten[51..55], 4i32.to_le_bytes());
+}